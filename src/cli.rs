@@ -1,83 +1,1397 @@
-use crate::hash::{nonce_to_bytes, HashWorkerFarm, Sha256Hash, TNonce};
-use crate::net::{PowLockError, PowServer};
-use std::time::Instant;
+use indicatif::HumanDuration;
+use log::{error, info, warn};
+use pow_key::gpu;
+use pow_key::hash::{
+    benchmark_partition, consistent_algorithms, detect_algorithm, estimate_hashrate,
+    estimate_hashrate_with, salt_base, HashAlgorithm, HashSolution, HashWorkerFarm, Nonce,
+    NonceByteOrder, NonceEncoding, PartitionScheme, ProgressStream, Sha256Hash, Sha256Hasher,
+    SolveGoal, SolveOutcome, TNonce,
+};
+use pow_key::net::{connection_error_message, LockStatus, PowLockError, PowServer};
+use pow_key::ranges::NonceRanges;
+use pow_key::state::SolveState;
+use pow_key::target::{self, digest_width, TargetSpec};
+use rustc_serialize::hex::ToHex;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-pub fn solve(base_string: String, target_hash: Sha256Hash, num_workers: u8) -> () {
-    let base = base_string.as_bytes().to_vec();
-    let hash_farm = HashWorkerFarm::new(base, target_hash.clone(), num_workers);
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    Csv,  // a header row, then one row per solve
+    Json, // one object per solve, with the reason when there's no solution
+}
+
+// how make_target writes the target
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetFormat {
+    Hex,
+    Base64,
+    Bytes, // the raw 32 bytes, for redirecting to a file
+}
+
+// what searches for the nonce; the GPU needs the "opencl" feature
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SolveDevice {
+    Cpu,
+    Gpu,
+}
+
+const CSV_HEADER: [&str; 7] = [
+    "base",
+    "target",
+    "nonce",
+    "nonce_hex",
+    "hash",
+    "attempts",
+    "elapsed_ms",
+];
+
+// options for `solve` beyond the challenge itself
+pub struct SolveOptions {
+    pub format: OutputFormat,
+    pub runs: u32,
+    pub salt_each_run: bool,
+    pub notify: bool,
+    pub worker_stack_size: usize,                 // bytes
+    pub verify_against: Option<(String, String)>, // device hostname and port
+    pub max_attempts: Option<u64>,
+    pub checkpoint_path: Option<PathBuf>,
+    pub resume_from: Option<NonceRanges>, // nonces already searched by an earlier solve
+    pub save_on_exit: Option<PathBuf>,
+    pub continue_from: Option<SolveState>, // progress is accumulated onto this when saving
+    pub print_resume_token: bool,
+    pub auto_algorithm: bool,
+    pub sample_nonce: Option<u64>, // a nonce known to solve the challenge, for --auto-algorithm
+    pub base_digest_only: bool,
+    pub progress_stream: ProgressStream,
+    pub show_best: bool,
+    pub device: SolveDevice,
+    pub give_up_if_slower_than: Option<Duration>,
+    pub deadline: Option<Duration>, // stop searching after this long
+    pub processes: Option<u8>,      // solve in this many child processes rather than only threads
+    pub otlp_endpoint: Option<String>, // an OpenTelemetry collector to send metrics to
+    pub nonce_byte_order: NonceByteOrder, // must match the lock's, see PowServer::set_nonce_byte_order
+    pub algorithm: HashAlgorithm,         // replaced by the detected one with auto_algorithm
+    pub interrupt: Option<Arc<AtomicBool>>, // set on Ctrl-C once `solve` handles it
+    pub pin_cores: bool,                  // see HashWorkerFarm::set_pin_cores
+}
+
+// how many nonces each algorithm is tried on when detecting it with the device
+const AUTO_ALGORITHM_NONCES: u64 = 100_000;
+
+// 128 + SIGINT, what a shell reports for a process ended by Ctrl-C
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+// the first Ctrl-C stops the search so its progress can be summarised; a
+// second one, ex: while waiting on a device, quits at once
+fn handle_interrupts() -> Arc<AtomicBool> {
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = interrupted.clone();
+    let handler = ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::SeqCst) {
+            std::process::exit(INTERRUPTED_EXIT_CODE);
+        }
+    });
+    if let Err(e) = handler {
+        warn!("Unable to handle Ctrl-C: {}", e);
+    }
+    interrupted
+}
+
+pub fn solve(
+    challenges: Vec<(Vec<u8>, SolveGoal)>,
+    num_workers: u8,
+    mut options: SolveOptions,
+) -> () {
+    let interrupted = handle_interrupts();
+    options.interrupt = Some(interrupted.clone());
+    if options.pin_cores {
+        warn_without_affinity();
+    }
+    let mut csv_writer = match options.format {
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(io::stdout());
+            writer
+                .write_record(CSV_HEADER)
+                .expect("Unable to write CSV output");
+            Some(writer)
+        }
+        OutputFormat::Text | OutputFormat::Json => None,
+    };
+    for (base, goal) in challenges {
+        for run in 0..options.runs {
+            solve_run(&base, &goal, run, num_workers, &options, &mut csv_writer);
+            if interrupted.load(Ordering::SeqCst) {
+                flush_output();
+                std::process::exit(INTERRUPTED_EXIT_CODE);
+            }
+        }
+    }
+}
+
+fn solve_run(
+    base: &[u8],
+    goal: &SolveGoal,
+    run: u32,
+    num_workers: u8,
+    options: &SolveOptions,
+    csv_writer: &mut Option<csv::Writer<io::Stdout>>,
+) -> () {
+    let run_base = match options.salt_each_run {
+        true => salt_base(base, run),
+        false => base.to_vec(),
+    };
+    let mut hash_farm = build_farm(&run_base, goal, num_workers, options);
+    let resume_token = Arc::new(Mutex::new(None));
+    if options.print_resume_token {
+        let state = match &options.continue_from {
+            Some(state) => state.clone(),
+            None => SolveState::new(run_base.clone(), goal),
+        };
+        let resume_token = resume_token.clone();
+        hash_farm.set_on_exit(state, move |state| {
+            *resume_token.lock().unwrap() = Some(state.to_token());
+        });
+    }
     let start_time = Instant::now();
-    let result = HashWorkerFarm::solve(Box::from(hash_farm));
-    match result {
-                Some(result) => println!(
-                    "Base string: {},\nSolved with nonce: {},\nAs bytes: {},\nHash: {}\nTarget: {}\nAttempts: {}\nTime (s): {}",
-                    base_string,
-                    result.nonce,
-                    result.nonce.as_hex_bytes(),
-                    result.hash,
-                    target_hash,
-                    result.attempts,
+    let mut detected_solution = None;
+    if options.auto_algorithm {
+        let (algorithm, solution) = auto_algorithm(&run_base, goal, options);
+        hash_farm.set_algorithm(algorithm);
+        detected_solution = solution;
+    }
+    // anything printed while setting up shows before the search blocks
+    flush_output();
+    let outcome = match (detected_solution, &options.verify_against) {
+        (Some(solution), _) => SolveOutcome::Solved(solution),
+        (None, Some((host, port))) => match connect(host.clone(), port.clone()) {
+            Some(server) => HashWorkerFarm::solve_verified_outcome(
+                Box::from(hash_farm),
+                device_verifier(server, options.nonce_byte_order),
+            ),
+            None => return,
+        },
+        (None, None) => search(&run_base, goal, num_workers, hash_farm, options),
+    };
+    if let SolveOutcome::Interrupted {
+        attempts,
+        highest_nonce,
+    } = outcome
+    {
+        // asked for with Ctrl-C, so it's output rather than a log message
+        eprintln!(
+            "{}",
+            interrupt_summary(start_time.elapsed(), attempts, highest_nonce)
+        );
+    }
+    let no_solution = match (&outcome, options.max_attempts) {
+        (SolveOutcome::MaxAttempts, Some(max_attempts)) => {
+            format!("attempt limit of {} reached", max_attempts)
+        }
+        _ => outcome.to_string(),
+    };
+    let reason = outcome.reason();
+    let result = outcome.solution();
+    if let Some(writer) = csv_writer {
+        writer
+            .write_record(csv_record(
+                &run_base,
+                goal,
+                &result,
+                options.nonce_byte_order,
+                start_time.elapsed(),
+            ))
+            .expect("Unable to write CSV output");
+        writer.flush().expect("Unable to write CSV output");
+    } else if options.format == OutputFormat::Json {
+        println!(
+            "{}",
+            json_record(
+                &run_base,
+                goal,
+                &result,
+                reason,
+                options.nonce_byte_order,
+                start_time.elapsed()
+            )
+        );
+    } else {
+        match &result {
+            Some(result) => {
+                println!(
+                    "{},\n{}\nTarget: {}\nTime (s): {}",
+                    base_description(&run_base, options.base_digest_only),
+                    result.display_with_order(options.nonce_byte_order),
+                    goal,
                     start_time.elapsed().as_secs()
+                );
+                // a prefix goal has no numeric target to beat
+                if let SolveGoal::Target(target) = goal {
+                    if let (Some(margin), Some(ratio)) = (
+                        result.hash.margin_below(target),
+                        result.hash.margin_ratio(target),
+                    ) {
+                        println!(
+                            "Margin below target: {} ({:.2}% of the target)",
+                            margin,
+                            ratio * 100.0
+                        );
+                    }
+                }
+            }
+            None => println!("No solution found: {}", no_solution),
+        }
+    }
+    // the farm only hands over its state when it ran the search
+    match resume_token.lock().unwrap().take() {
+        Some(Ok(token)) if options.format != OutputFormat::Text => {
+            eprintln!("Resume token: {}", token)
+        }
+        Some(Ok(token)) => println!("Resume token: {}", token),
+        Some(Err(e)) => warn!("Unable to make a resume token: {}", e),
+        None => {}
+    }
+    // the result is out before notifying, or the next run, can block
+    flush_output();
+    if options.notify {
+        let (title, body) = notification_payload(&result, start_time.elapsed());
+        send_notification(&title, &body);
+    }
+    if let Some(endpoint) = &options.otlp_endpoint {
+        send_telemetry(endpoint, &result, start_time.elapsed());
+    }
+}
+
+fn interrupt_summary(elapsed: Duration, attempts: u64, highest_nonce: Nonce) -> String {
+    format!(
+        "Interrupted after {}: {} attempts, reaching nonce {}",
+        HumanDuration(elapsed),
+        attempts,
+        highest_nonce
+    )
+}
+
+// writes out anything buffered. Whole lines are already flushed, since stdout
+// is line buffered, so this only matters for output without a newline
+fn flush_output() -> () {
+    let _ = io::stdout().flush();
+    let _ = io::stderr().flush();
+}
+
+// a farm for the challenge, set up with everything in the options
+fn build_farm(
+    run_base: &[u8],
+    goal: &SolveGoal,
+    num_workers: u8,
+    options: &SolveOptions,
+) -> HashWorkerFarm {
+    let mut hash_farm = match &options.resume_from {
+        Some(covered) => HashWorkerFarm::resume(
+            run_base.to_vec(),
+            goal.clone(),
+            num_workers,
+            covered.clone(),
+        ),
+        None => HashWorkerFarm::new_with_goal(run_base.to_vec(), goal.clone(), num_workers),
+    };
+    hash_farm.set_checkpoint_path(options.checkpoint_path.clone());
+    hash_farm.set_worker_stack_size(options.worker_stack_size);
+    hash_farm.set_pin_cores(options.pin_cores);
+    hash_farm.set_max_attempts(options.max_attempts);
+    hash_farm.set_progress_stream(options.progress_stream);
+    hash_farm.set_show_best(options.show_best);
+    hash_farm.set_give_up_if_slower_than(options.give_up_if_slower_than);
+    hash_farm.set_deadline(options.deadline);
+    hash_farm.set_nonce_byte_order(options.nonce_byte_order);
+    hash_farm.set_algorithm(options.algorithm);
+    if let Some(interrupt) = &options.interrupt {
+        hash_farm.set_interrupt(interrupt.clone());
+    }
+    if let Some(path) = &options.save_on_exit {
+        let state = match &options.continue_from {
+            Some(state) => state.clone(),
+            None => SolveState::new(run_base.to_vec(), goal),
+        };
+        hash_farm.set_save_on_exit(path.clone(), state);
+    }
+    hash_farm
+}
+
+// solves on the chosen device, without the lock's help
+fn search(
+    run_base: &[u8],
+    goal: &SolveGoal,
+    num_workers: u8,
+    hash_farm: HashWorkerFarm,
+    options: &SolveOptions,
+) -> SolveOutcome {
+    if let Some(processes) = options.processes {
+        match solve_in_processes(run_base, goal, processes, num_workers, options) {
+            Ok(Some(solution)) => return SolveOutcome::Solved(solution),
+            // every process searched its share
+            Ok(None) => return SolveOutcome::Exhausted,
+            Err(e) => warn!("{}; solving in this process instead", e),
+        }
+    }
+    if options.device == SolveDevice::Gpu && options.nonce_byte_order == NonceByteOrder::Big {
+        warn!("The GPU only hashes little-endian nonces; solving on the CPU instead");
+    } else if options.device == SolveDevice::Gpu && options.algorithm != HashAlgorithm::Sha256 {
+        warn!(
+            "The GPU only hashes with {}; solving on the CPU instead",
+            HashAlgorithm::Sha256
+        );
+    } else if options.device == SolveDevice::Gpu {
+        match gpu::solve(
+            run_base,
+            goal,
+            options.max_attempts,
+            gpu::DEFAULT_BATCH_SIZE,
+            options.interrupt.as_deref(),
+        ) {
+            Ok(outcome) => return outcome,
+            Err(e) => warn!("{}; solving on the CPU instead", e),
+        }
+    }
+    HashWorkerFarm::solve_outcome(Box::from(hash_farm))
+}
+
+// identifies the base in solve output: the base itself, unless it's binary or
+// only the digest is wanted, and its SHA-256, which stays short however long
+// the base is
+fn base_description(base: &[u8], digest_only: bool) -> String {
+    let digest = Sha256Hasher::hash_bytes(base);
+    match (digest_only, std::str::from_utf8(base)) {
+        (false, Ok(text)) => format!("Base string: {},\nBase digest: {}", text, digest),
+        _ => format!("Base digest: {}", digest),
+    }
+}
+
+// picks the algorithm to solve with: the one the device unlocks with, if we can
+// talk to it, otherwise the first one consistent with the sample nonce. When
+// the device unlocked, its solution is returned too
+fn auto_algorithm(
+    base: &[u8],
+    goal: &SolveGoal,
+    options: &SolveOptions,
+) -> (HashAlgorithm, Option<HashSolution>) {
+    if let Some(server) = options
+        .verify_against
+        .as_ref()
+        .and_then(|(host, port)| connect(host.clone(), port.clone()))
+    {
+        let verifier = device_verifier(server, options.nonce_byte_order);
+        match detect_algorithm(base, goal, AUTO_ALGORITHM_NONCES, verifier) {
+            Some((algorithm, solution)) => {
+                info!("Device accepted a {} solution", algorithm);
+                return (algorithm, Some(solution));
+            }
+            None => warn!(
+                "No algorithm found a solution the device accepts in the first {} nonces",
+                AUTO_ALGORITHM_NONCES
+            ),
+        }
+    }
+    if let Some(nonce) = options.sample_nonce {
+        let algorithms = consistent_algorithms(base, goal, nonce);
+        match algorithms.first() {
+            Some(&algorithm) => {
+                let names: Vec<String> = algorithms.iter().map(|a| a.to_string()).collect();
+                info!(
+                    "Algorithms consistent with nonce {}: {}",
+                    nonce,
+                    names.join(", ")
+                );
+                return (algorithm, None);
+            }
+            None => warn!("No algorithm is consistent with nonce {}", nonce),
+        }
+    }
+    info!("Solving with {}", HashAlgorithm::Sha256);
+    (HashAlgorithm::Sha256, None)
+}
+
+// one CSV row matching CSV_HEADER; the solution columns are empty when there is none
+fn csv_record(
+    base: &[u8],
+    goal: &SolveGoal,
+    result: &Option<HashSolution>,
+    order: NonceByteOrder,
+    elapsed: Duration,
+) -> Vec<String> {
+    let mut record = vec![String::from_utf8_lossy(base).to_string(), goal.to_string()];
+    match result {
+        Some(solution) => record.extend(vec![
+            solution.nonce.to_string(),
+            solution.nonce.as_hex_bytes(order),
+            solution.hash.to_string(),
+            solution.attempts.to_string(),
+        ]),
+        None => record.extend(vec![String::new(); 4]),
+    }
+    record.push(elapsed.as_millis().to_string());
+    record
+}
+
+#[derive(Debug, Serialize)]
+struct JsonSolution {
+    base: String,
+    nonce: Nonce,
+    nonce_hex: String,
+    hash: String,
+    target: String,
+    attempts: u64,
+    elapsed_secs: f64,
+}
+
+// what's written instead of a solution; `solution` is always null
+#[derive(Debug, Serialize)]
+struct JsonNoSolution {
+    solution: Option<JsonSolution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>, // ex: max_attempts
+}
+
+// the `--format json` report of a solve: the solution's fields, or
+// {"solution":null} with the reason there's none
+fn json_record(
+    base: &[u8],
+    goal: &SolveGoal,
+    result: &Option<HashSolution>,
+    reason: Option<&str>,
+    order: NonceByteOrder,
+    elapsed: Duration,
+) -> String {
+    match result {
+        Some(solution) => serde_json::to_string(&JsonSolution {
+            base: String::from_utf8_lossy(base).to_string(),
+            nonce: solution.nonce,
+            nonce_hex: solution.nonce.as_hex_bytes(order),
+            hash: solution.hash.to_string(),
+            target: goal.to_string(),
+            attempts: solution.attempts,
+            elapsed_secs: elapsed.as_secs_f64(),
+        }),
+        None => serde_json::to_string(&JsonNoSolution {
+            solution: None,
+            reason: reason.map(String::from),
+        }),
+    }
+    .unwrap()
+}
+
+// the base given to `solve --base`: the value itself, or with a leading @
+// the contents of the file it names, or with - everything on `stdin`. A
+// single trailing newline is left off of what's read
+pub fn read_base_arg<R: Read>(value: &str, mut stdin: R) -> Result<Vec<u8>, String> {
+    let mut base = vec![];
+    if value == "-" {
+        stdin
+            .read_to_end(&mut base)
+            .map_err(|e| format!("Unable to read the base from stdin: {}", e))?;
+    } else if let Some(path) = value.strip_prefix('@') {
+        base = std::fs::read(path).map_err(|e| format!("Unable to open {}: {}", path, e))?;
+    } else {
+        return Ok(value.as_bytes().to_vec());
+    }
+    if base.ends_with(b"\n") {
+        base.pop();
+        if base.ends_with(b"\r") {
+            base.pop();
+        }
+    }
+    Ok(base)
+}
+
+// reads `solve --batch` challenges: a CSV file with a `base,target` header and
+// one challenge per row, the target given as 64 hex characters
+pub fn read_batch(path: &str) -> Result<Vec<(Vec<u8>, SolveGoal)>, String> {
+    let file = File::open(path).map_err(|e| format!("Unable to open {}: {}", path, e))?;
+    let mut reader = csv::ReaderBuilder::new().from_reader(file);
+    let mut challenges = vec![];
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Invalid CSV in {}: {}", path, e))?;
+        match (record.get(0), record.get(1)) {
+            (Some(base), Some(target)) => challenges.push((
+                base.as_bytes().to_vec(),
+                SolveGoal::Target(
+                    Sha256Hash::from_str(target)
+                        .map_err(|e| format!("Invalid target on row {}: {}", i + 1, e))?,
+                ),
+            )),
+            _ => return Err(format!("Row {} needs a base and a target", i + 1)),
+        }
+    }
+    Ok(challenges)
+}
+
+// a `verify --batch` entry, ex: {"base":"helloworld","nonce":1234,"target":"000fff..."}
+#[derive(Debug, Deserialize)]
+struct VerifyEntry {
+    base: String,
+    nonce: Nonce,
+    target: String, // 64 hex characters
+}
+
+// reads `verify --batch` entries as JSON lines, or as CSV with a
+// `base,nonce,target` header when the first line isn't a JSON object
+fn read_verify_batch(contents: &str) -> Result<Vec<VerifyEntry>, String> {
+    if contents.trim_start().starts_with('{') {
+        return contents
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                serde_json::from_str(line)
+                    .map_err(|e| format!("Invalid JSON on line {}: {}", i + 1, e))
+            })
+            .collect();
+    }
+    let mut reader = csv::ReaderBuilder::new().from_reader(contents.as_bytes());
+    let mut entries = vec![];
+    for (i, record) in reader.records().enumerate() {
+        let record = record.map_err(|e| format!("Invalid CSV: {}", e))?;
+        match (record.get(0), record.get(1), record.get(2)) {
+            (Some(base), Some(nonce), Some(target)) => entries.push(VerifyEntry {
+                base: base.to_string(),
+                nonce: nonce
+                    .parse()
+                    .map_err(|e| format!("Invalid nonce on row {}: {}", i + 1, e))?,
+                target: target.to_string(),
+            }),
+            _ => return Err(format!("Row {} needs a base, a nonce and a target", i + 1)),
+        }
+    }
+    Ok(entries)
+}
+
+// checks a nonce locally before trying it on the lock; returns whether it solves the target
+pub fn verify(base: Vec<u8>, target: Sha256Hash, nonce: Nonce, algorithm: HashAlgorithm) -> bool {
+    let hasher = Sha256Hasher::with_algorithm(base, algorithm);
+    println!("Hash: {}", hasher.hash_with_nonce(nonce));
+    let valid = hasher.verify(nonce, &target);
+    match valid {
+        true => println!("Nonce {} solves the target", nonce),
+        false => println!(
+            "Nonce {} doesn't solve the target; the hash isn't below {}",
+            nonce, target
+        ),
+    }
+    valid
+}
+
+// checks every entry's nonce against its challenge, writing a line for each
+// and a summary; returns whether they were all valid solutions
+pub fn verify_batch(
+    contents: &str,
+    algorithm: HashAlgorithm,
+    mut output: impl Write,
+) -> Result<bool, String> {
+    let entries = read_verify_batch(contents)?;
+    let mut valid = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        let target = Sha256Hash::from_str(&entry.target)
+            .map_err(|e| format!("Invalid target in entry {}: {}", i + 1, e))?;
+        let hasher = Sha256Hasher::with_algorithm(entry.base.as_bytes().to_vec(), algorithm);
+        let hash = hasher.hash_with_nonce(entry.nonce);
+        let line = match SolveGoal::Target(target).is_met_by(&hash) {
+            true => {
+                valid += 1;
+                format!("{}: valid, nonce {} hashes to {}", i + 1, entry.nonce, hash)
+            }
+            false => format!(
+                "{}: invalid, nonce {} hashes to {}, which isn't below {}",
+                i + 1,
+                entry.nonce,
+                hash,
+                entry.target
+            ),
+        };
+        writeln!(output, "{}", line).map_err(|e| e.to_string())?;
+    }
+    writeln!(output, "{} of {} valid", valid, entries.len()).map_err(|e| e.to_string())?;
+    Ok(valid == entries.len())
+}
+
+// a `solve --stdin-jsonl` challenge, ex: {"base":"helloworld","target":"000fff..."}
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonlChallenge {
+    base: String,
+    target: String, // 64 hex characters
+}
+
+// the `solve --stdin-jsonl` result for one input line; there's an error
+// instead of a solution when the line is malformed or has no solution
+#[derive(Debug, Serialize, Deserialize)]
+struct JsonlResult {
+    line: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<Nonce>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce_hex: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attempts: Option<u64>,
+    elapsed_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// solves each JSONL challenge as it's read, writing one JSON result line per
+// challenge in the same order; blank lines are skipped
+pub fn solve_jsonl(
+    input: impl BufRead,
+    mut output: impl Write,
+    num_workers: u8,
+    options: &SolveOptions,
+) -> io::Result<()> {
+    for (i, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let result = jsonl_result(i + 1, &line, num_workers, options);
+        writeln!(output, "{}", serde_json::to_string(&result).unwrap())?;
+        // whoever is reading may be waiting on this result before sending more
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn jsonl_result(
+    line_number: usize,
+    line: &str,
+    num_workers: u8,
+    options: &SolveOptions,
+) -> JsonlResult {
+    let start_time = Instant::now();
+    let mut result = JsonlResult {
+        line: line_number,
+        nonce: None,
+        nonce_hex: None,
+        hash: None,
+        attempts: None,
+        elapsed_ms: 0,
+        error: None,
+    };
+    let challenge = serde_json::from_str::<JsonlChallenge>(line)
+        .map_err(|e| format!("Invalid challenge: {}", e))
+        .and_then(|challenge| match Sha256Hash::from_str(&challenge.target) {
+            Ok(target) => Ok((challenge.base.into_bytes(), SolveGoal::Target(target))),
+            Err(e) => Err(format!("Invalid target: {}", e)),
+        });
+    match challenge {
+        Ok((base, goal)) => {
+            let hash_farm = build_farm(&base, &goal, num_workers, options);
+            match search(&base, &goal, num_workers, hash_farm, options) {
+                SolveOutcome::Solved(solution) => {
+                    result.nonce = Some(solution.nonce);
+                    result.nonce_hex = Some(solution.nonce.as_hex_bytes(options.nonce_byte_order));
+                    result.hash = Some(solution.hash.to_string());
+                    result.attempts = Some(solution.attempts);
+                }
+                outcome => result.error = Some(format!("No solution found: {}", outcome)),
+            }
+        }
+        Err(e) => result.error = Some(e),
+    }
+    result.elapsed_ms = start_time.elapsed().as_millis() as u64;
+    result
+}
+
+// splits the nonces between child processes, each running `solve
+// --stdin-jsonl` over its own range with `num_workers` threads. The first
+// solution reported wins and the other processes are killed
+fn solve_in_processes(
+    base: &[u8],
+    goal: &SolveGoal,
+    processes: u8,
+    num_workers: u8,
+    options: &SolveOptions,
+) -> Result<Option<HashSolution>, String> {
+    let target = match goal {
+        SolveGoal::Target(target) => target,
+        SolveGoal::Prefix(_) => return Err("Solving in processes needs a target".to_string()),
+    };
+    let challenge = JsonlChallenge {
+        base: std::str::from_utf8(base)
+            .map_err(|_| "Solving in processes needs a text base".to_string())?
+            .to_string(),
+        target: target.to_string(),
+    };
+    let challenge = serde_json::to_string(&challenge).unwrap();
+    let exe = std::env::current_exe().map_err(|e| format!("Unable to find pow_key: {}", e))?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut children = vec![];
+    let mut spawned = Ok(());
+    for (start, end) in NonceRanges::new().split_uncovered(0, std::u64::MAX, processes) {
+        let mut command = Command::new(&exe);
+        command
+            .args(["solve", "--stdin-jsonl", "--start", &start.to_string()])
+            .args(["--count", &(end - start).to_string()])
+            .args(["-p", &num_workers.to_string()])
+            .args(["--algo", &options.algorithm.to_string()])
+            // progress bars from several processes would just garble each other
+            .arg("--quiet");
+        if options.nonce_byte_order == NonceByteOrder::Big {
+            command.arg("--big-endian");
+        }
+        let child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            // so the child's errors and warnings are seen
+            .stderr(Stdio::inherit())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                spawned = Err(format!("Unable to start a solve process: {}", e));
+                break;
+            }
+        };
+        // closing stdin after the challenge lets the child exit once it's solved
+        let mut stdin = child.stdin.take().unwrap();
+        let _ = writeln!(stdin, "{}", challenge);
+        let stdout = child.stdout.take().unwrap();
+        let sender = sender.clone();
+        std::thread::spawn(move || {
+            let mut line = String::new();
+            let _ = io::BufReader::new(stdout).read_line(&mut line);
+            let _ = sender.send(line);
+        });
+        children.push(child);
+    }
+    drop(sender);
+
+    let mut solution = None;
+    if spawned.is_ok() {
+        let mut hasher = Sha256Hasher::with_algorithm(base.to_vec(), options.algorithm);
+        hasher.set_nonce_byte_order(options.nonce_byte_order);
+        // a process that fails or finds nothing in its range reports no nonce
+        for line in receiver.iter() {
+            let result = match serde_json::from_str::<JsonlResult>(&line) {
+                Ok(result) => result,
+                Err(_) => continue,
+            };
+            if let (Some(nonce), Some(attempts)) = (result.nonce, result.attempts) {
+                let hash = hasher.hash_with_nonce(nonce);
+                // the child is checked rather than trusted
+                if goal.is_met_by(&hash) {
+                    solution = Some(HashSolution {
+                        nonce: nonce,
+                        nonce_high: 0,
+                        attempts: attempts,
+                        hash: hash,
+                    });
+                    break;
+                }
+            }
+        }
+    }
+    // without a solution every child has finished; one that exited
+    // unsuccessfully failed rather than searching its whole range
+    let mut failed = None;
+    for child in &mut children {
+        if solution.is_some() || spawned.is_err() {
+            let _ = child.kill();
+        }
+        match child.wait() {
+            Ok(status) if !status.success() && solution.is_none() => failed = Some(status),
+            _ => {}
+        }
+    }
+    spawned?;
+    match failed {
+        Some(status) => Err(format!("A solve process failed ({})", status)),
+        None => Ok(solution),
+    }
+}
+
+// accepts a candidate solution only if the device unlocks with it
+fn device_verifier(
+    mut server: PowServer,
+    order: NonceByteOrder,
+) -> impl FnMut(&HashSolution) -> bool {
+    server.set_nonce_byte_order(order);
+    move |solution| match server.unlock(solution.nonce) {
+        Ok(_) => true,
+        Err(e) => {
+            match e {
+                PowLockError::Unsuccessful => warn!(
+                    "Device rejected nonce {}; it may expect a different nonce encoding. Continuing search",
+                    solution.nonce
+                ),
+                _ => warn!(
+                    "Unable to verify nonce {} with the device. Continuing search",
+                    solution.nonce
                 ),
-                None => println!("No solution found"),
+            }
+            false
+        }
+    }
+}
+
+// builds the title and body of the desktop notification sent when a solve finishes
+fn notification_payload(result: &Option<HashSolution>, elapsed: Duration) -> (String, String) {
+    match result {
+        Some(solution) => (
+            "Solve complete".to_string(),
+            format!(
+                "Solved with nonce {} in {}",
+                solution.nonce,
+                HumanDuration(elapsed)
+            ),
+        ),
+        None => (
+            "Solve failed".to_string(),
+            format!("No solution found after {}", HumanDuration(elapsed)),
+        ),
+    }
+}
+
+#[cfg(feature = "notify")]
+fn send_notification(title: &str, body: &str) -> () {
+    // notifications are best effort; platforms without a notification
+    // service just don't get one
+    if let Err(e) = notify_rust::Notification::new()
+        .summary(title)
+        .body(body)
+        .show()
+    {
+        log::debug!("Unable to show a notification: {}", e);
+    }
+}
+
+#[cfg(not(feature = "notify"))]
+fn send_notification(_title: &str, _body: &str) -> () {
+    warn!("Desktop notifications require building with the \"notify\" feature");
+}
+
+#[cfg(feature = "telemetry")]
+fn send_telemetry(endpoint: &str, result: &Option<HashSolution>, elapsed: Duration) -> () {
+    let metrics = pow_key::telemetry::SolveMetrics {
+        duration: elapsed,
+        attempts: result.as_ref().map_or(0, |solution| solution.attempts),
+        solved: result.is_some(),
+    };
+    // telemetry is best effort; an unreachable collector shouldn't fail the solve
+    if let Err(e) = pow_key::telemetry::export(endpoint, &metrics) {
+        warn!("Unable to send telemetry to {}: {}", endpoint, e);
+    }
+}
+
+#[cfg(not(feature = "telemetry"))]
+fn send_telemetry(_endpoint: &str, _result: &Option<HashSolution>, _elapsed: Duration) -> () {
+    warn!("Sending telemetry requires building with the \"telemetry\" feature");
+}
+
+#[cfg(feature = "affinity")]
+fn warn_without_affinity() -> () {}
+
+#[cfg(not(feature = "affinity"))]
+fn warn_without_affinity() -> () {
+    warn!("Pinning workers to cores requires building with the \"affinity\" feature");
+}
+
+// one worker per core, as many as a u8 allows
+pub fn auto_worker_count(cores: usize) -> u8 {
+    cores.clamp(1, std::u8::MAX as usize) as u8
+}
+
+// a worker count is either absolute, ex: 4, a fraction of the cores,
+// ex: 50% or 0.5x, rounded down, or auto for one per core
+pub fn parse_worker_count(value: &str, cores: usize) -> Result<u8, String> {
+    let fraction = if value == "auto" {
+        return Ok(auto_worker_count(cores));
+    } else if let Some(percent) = value.strip_suffix('%') {
+        percent.parse::<f64>().map(|p| p / 100.0)
+    } else if let Some(multiple) = value.strip_suffix('x') {
+        multiple.parse::<f64>()
+    } else {
+        return match value.parse::<u8>() {
+            Ok(0) => Err("At least 1 worker is required".to_string()),
+            Ok(count) => Ok(count),
+            Err(e) => Err(format!("Invalid worker count {}: {}", value, e)),
+        };
+    };
+    let fraction = fraction.map_err(|e| format!("Invalid worker count {}: {}", value, e))?;
+    let count = (fraction * cores as f64).floor();
+    if count.is_nan() || count < 1.0 {
+        return Err(format!(
+            "{} of {} cores is less than 1 worker",
+            value, cores
+        ));
+    }
+    if count > std::u8::MAX as f64 {
+        return Err(format!("At most {} workers are supported", std::u8::MAX));
+    }
+    Ok(count as u8)
+}
+
+pub fn hash_file(path: String) -> () {
+    let file = match File::open(&path) {
+        Ok(f) => f,
+        Err(e) => {
+            error!("Unable to open {}: {}", path, e);
+            return;
+        }
+    };
+    match Sha256Hasher::hash_reader(file) {
+        Ok(hash) => println!("{}", hash),
+        Err(e) => error!("Unable to read {}: {}", path, e),
+    }
+}
+
+pub fn probe(base: Vec<u8>, goal: SolveGoal, start: u64, end: u64, samples: u64) -> () {
+    let estimate = pow_key::hash::probe(&base, &goal, start, end, samples);
+    println!(
+        "Sampled {} nonces, {} met the target",
+        estimate.sampled, estimate.solutions
+    );
+    if estimate.solutions == 0 {
+        println!(
+            "No solutions sampled; the range likely has fewer than {:.0}",
+            (end - start) as f64 / samples as f64
+        );
+        return;
+    }
+    let expected = estimate.expected_solutions(end - start);
+    println!("Estimated solution density: {:e}", estimate.density());
+    println!("Estimated solutions in range: {:.0}", expected);
+    println!(
+        "Chance the range holds a solution: {:.1}%",
+        (1.0 - (-expected).exp()) * 100.0
+    );
+}
+
+pub fn benchmark(
+    base: Vec<u8>,
+    goal: SolveGoal,
+    num_workers: u8,
+    partitions: Vec<PartitionScheme>,
+    cluster_below: u64,
+) -> () {
+    println!(
+        "Timing solves that only accept nonces below {} with {} workers",
+        cluster_below, num_workers
+    );
+    let mut times = vec![];
+    for partition in partitions {
+        let (result, elapsed) =
+            benchmark_partition(&base, &goal, num_workers, partition, cluster_below);
+        match result {
+            Some(solution) => {
+                println!(
+                    "{:?}: nonce {} after {} attempts in {:.3}s",
+                    partition,
+                    solution.nonce,
+                    solution.attempts,
+                    elapsed.as_secs_f64()
+                );
+                times.push(elapsed);
+            }
+            None => println!("{:?}: no solution below {}", partition, cluster_below),
+        }
+    }
+    if let [contiguous, interleaved] = times[..] {
+        println!(
+            "Interleaved speedup: {:.2}x",
+            contiguous.as_secs_f64() / interleaved.as_secs_f64()
+        );
+    }
+}
+
+// measures the hashrate with 1, 2, 4, ... workers up to `max_workers`,
+// `length` seconds each, to show how well hashing scales on this machine
+pub fn bench(max_workers: u8, length: u64) -> () {
+    let mut results = vec![];
+    for num_workers in bench_worker_counts(max_workers) {
+        info!(
+            "Measuring the hashrate with {} workers for {} seconds",
+            num_workers, length
+        );
+        results.push((num_workers, estimate_hashrate(num_workers, length)));
+    }
+    print!("{}", format_scaling_table(&results));
+}
+
+// powers of two below `max_workers`, then `max_workers` itself
+pub fn bench_worker_counts(max_workers: u8) -> Vec<u8> {
+    let mut counts: Vec<u8> = (0..8)
+        .map(|power| 1u8 << power)
+        .take_while(|&count| count < max_workers)
+        .collect();
+    counts.push(max_workers.max(1));
+    counts
+}
+
+// one row per worker count, with its speedup over the first row's
+pub fn format_scaling_table(results: &[(u8, u32)]) -> String {
+    let mut table = format!("{:>7} {:>12} {:>8}\n", "Workers", "H/s", "Speedup");
+    let baseline = results.first().map_or(0, |&(_, rate)| rate);
+    for &(num_workers, rate) in results {
+        let speedup = match baseline {
+            0 => "-".to_string(),
+            _ => format!("{:.2}x", rate as f64 / baseline as f64),
+        };
+        table.push_str(&format!("{:>7} {:>12} {:>8}\n", num_workers, rate, speedup));
+    }
+    table
+}
+
+// how long the hashrate is measured for `make_target --estimate`
+const ESTIMATE_PROBE_SECONDS: u64 = 3;
+
+// with `estimate_workers`, also measures the hashrate and logs how long
+// solving the target is expected to take and, given the duration the target
+// was made for as `within`, the chance of solving within it. Only the target
+// goes to stdout, so a target written as bytes isn't corrupted
+pub fn make_target(
+    target: Sha256Hash,
+    format: TargetFormat,
+    estimate_workers: Option<u8>,
+    within: Option<Duration>,
+) -> () {
+    write_target(io::stdout(), &target, format).expect("Unable to write the target");
+    if let Some(num_workers) = estimate_workers {
+        info!(
+            "Measuring the hashrate for {} seconds",
+            ESTIMATE_PROBE_SECONDS
+        );
+        let rate = estimate_hashrate(num_workers, ESTIMATE_PROBE_SECONDS);
+        info!("Hashrate: {} H/s", rate);
+        info!(
+            "Expected time: {}",
+            format_solve_estimate(target.expected_attempts_to_solve(), rate)
+        );
+        info!(
+            "p90 time: {}",
+            format_solve_estimate(target.p90_attempts_to_solve(), rate)
+        );
+        info!(
+            "p99 time: {}",
+            format_solve_estimate(target.p99_attempts_to_solve(), rate)
+        );
+        if let Some(within) = within {
+            let attempts = (rate as f64 * within.as_secs_f64()) as u64;
+            info!(
+                "Chance of solving within {}: {:.1}%",
+                HumanDuration(within),
+                target.solve_probability(attempts) * 100.0
+            );
+        }
+    }
+}
+
+pub fn write_target<W: Write>(
+    mut out: W,
+    target: &Sha256Hash,
+    format: TargetFormat,
+) -> io::Result<()> {
+    match format {
+        TargetFormat::Hex => writeln!(out, "{}", target),
+        TargetFormat::Base64 => writeln!(out, "{}", target.to_base64()),
+        TargetFormat::Bytes => out.write_all(target.as_bytes()),
+    }
+}
+
+// the target for solving in `duration` at this machine's hashrate, measured
+// with `num_workers` workers first
+pub fn auto_hashrate_target(duration: &str, num_workers: u8) -> Result<Sha256Hash, String> {
+    info!(
+        "Measuring the hashrate for {} seconds",
+        ESTIMATE_PROBE_SECONDS
+    );
+    let rate = estimate_hashrate(num_workers, ESTIMATE_PROBE_SECONDS);
+    // stdout is left for the target alone
+    info!("Hashrate: {} H/s", rate);
+    target_for_measured_rate(duration, rate)
+}
+
+fn target_for_measured_rate(duration: &str, rate: u32) -> Result<Sha256Hash, String> {
+    TargetSpec::Duration {
+        duration: duration.to_string(),
+        hash_rate: rate as u64,
+    }
+    .resolve()
+}
+
+// how long `attempts` take at `rate` H/s; HumanDuration counts whole
+// seconds, so anything shorter is given in milliseconds
+pub fn format_solve_estimate(attempts: u64, rate: u32) -> String {
+    if rate == 0 {
+        return "unknown".to_string();
+    }
+    let time = Duration::from_secs_f64(attempts as f64 / rate as f64);
+    match time < Duration::from_secs(1) {
+        true => format!("{} ms", time.as_millis()),
+        false => HumanDuration(time).to_string(),
+    }
+}
+
+pub fn estimate_cost(target: Sha256Hash, hash_rate: u64, watts: f64, price_per_kwh: f64) -> () {
+    match target::estimate_cost(&target, hash_rate, watts, price_per_kwh) {
+        Ok(estimate) => {
+            println!("Expected time: {}", HumanDuration(estimate.expected_time));
+            println!("Energy: {:.3} kWh", estimate.energy_kwh);
+            println!("Cost: ${:.2}", estimate.cost);
+        }
+        Err(e) => error!("{}", e),
+    }
+}
+
+pub fn match_difficulty(from_target: String, from_algorithm: String, to_algorithm: String) -> () {
+    let widths =
+        digest_width(&from_algorithm).and_then(|from| Ok((from, digest_width(&to_algorithm)?)));
+    match widths.and_then(|(from, to)| target::match_difficulty(&from_target, from, to)) {
+        Ok(target) => println!("{}", target),
+        Err(e) => error!("{}", e),
+    }
+}
+
+pub fn encode_nonce(nonce: Nonce, encoding: NonceEncoding) -> () {
+    match encoding.encode(nonce) {
+        Ok(bytes) => {
+            println!("Hex: {}", bytes.to_hex());
+            println!("Bytes: {:?}", bytes);
+        }
+        Err(e) => error!("{}", e),
+    }
+}
+
+pub fn leading_zeros(hash: Sha256Hash) -> () {
+    println!("{}", hash.leading_zero_bits());
+}
+
+#[cfg(feature = "serve")]
+pub fn serve(address: String, max_concurrent_solves: usize) -> () {
+    match pow_key::serve::SolveService::bind(&address, max_concurrent_solves) {
+        Ok(service) => {
+            info!("Listening on {}", address);
+            service.run();
+        }
+        Err(e) => error!("Unable to listen on {}: {}", address, e),
     }
 }
 
-pub fn make_target(duration_string: String, hash_rate: u64) -> () {
-    let result = Sha256Hash::target_for_duration(duration_string, hash_rate);
-    println!("{}", result);
+#[cfg(not(feature = "serve"))]
+pub fn serve(_address: String, _max_concurrent_solves: usize) -> () {
+    warn!("The serve command requires building with the \"serve\" feature");
 }
 
-pub fn hashrate_test(num_workers: u8, length: u64) -> () {
+// `base` stands in for the real challenge's base, when given
+pub fn hashrate_test(
+    num_workers: u8,
+    length: u64,
+    base: Option<Vec<u8>>,
+    warmup: Duration,
+    pin_cores: bool,
+) -> () {
     if length < 20 {
-        println!("Run the hashrate test for at least 20 seconds");
+        error!("Run the hashrate test for at least 20 seconds");
         return;
     }
-    let test_hash_farm = HashWorkerFarm::new_test(num_workers);
-    println!("Hashrate: {} H/s", test_hash_farm.run_test(length));
+    let mut test_hash_farm = match base {
+        Some(base) => HashWorkerFarm::new_test_with_base(base, num_workers),
+        None => HashWorkerFarm::new_test(num_workers),
+    };
+    test_hash_farm.set_warmup(warmup);
+    if pin_cores {
+        warn_without_affinity();
+        test_hash_farm.set_pin_cores(true);
+    }
+    // Ctrl-C ends the test early with the rate measured so far
+    test_hash_farm.set_interrupt(handle_interrupts());
+    info!("Measuring the hashrate for {} seconds", length);
+    println!(
+        "Hashrate: {} H/s",
+        estimate_hashrate_with(test_hash_farm, length)
+    );
+}
+
+// the base and target the lock is waiting on, to solve in place of ones given
+// on the command line
+pub fn challenge_from_device(server: &mut PowServer) -> Result<(Vec<u8>, SolveGoal), String> {
+    let base = server.get_base().map_err(|e| e.to_string())?;
+    let target = server
+        .get_target()
+        .map_err(|e| e.to_string())?
+        .trim()
+        .parse::<Sha256Hash>()
+        .map_err(|_| "Invalid target from device".to_string())?;
+    Ok((base.into_bytes(), SolveGoal::Target(target)))
+}
+
+// connects to the lock, explaining why if we can't
+pub fn connect(host: String, port: String) -> Option<PowServer> {
+    match PowServer::connect(host.clone(), port.clone()) {
+        Ok(server) => Some(server),
+        Err(PowLockError::Connection(kind)) => {
+            error!(
+                "Unable to connect with lock at {}:{}: {}",
+                host,
+                port,
+                connection_error_message(kind)
+            );
+            None
+        }
+        Err(_) => {
+            error!("Unable to connect with lock at {}:{}", host, port);
+            None
+        }
+    }
 }
 
 pub fn get_status(mut server: PowServer) -> () {
     match server.get_status() {
-        Ok(s) => println!("{}", s),
-        Err(e) => match e {
-            PowLockError::Connection => println!("Error connecting with lock"),
-            _ => println!("Unknown error"),
-        },
+        Ok(status) => println!("{}", status),
+        Err(e) => error!("{}", e),
+    }
+}
+
+pub fn wait(
+    mut server: PowServer,
+    state: LockStatus,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> () {
+    match server.wait_for_status(state, poll_interval, timeout) {
+        Ok(_) => println!("{}", state),
+        Err(PowLockError::Timeout) => error!(
+            "Still not {} after {}",
+            state.to_string().to_lowercase(),
+            HumanDuration(timeout)
+        ),
+        Err(e) => error!("{}", e),
+    }
+}
+
+pub fn ping(mut server: PowServer, count: u32) -> () {
+    let mut times = vec![];
+    for (i, sample) in server.ping(count).into_iter().enumerate() {
+        match sample {
+            Ok(time) => {
+                println!("status request {}: {:.1} ms", i + 1, as_millis(time));
+                times.push(time);
+            }
+            Err(e) => println!("status request {}: {}", i + 1, e),
+        }
+    }
+    if times.is_empty() {
+        println!("No successful status requests");
+        return;
     }
+    let total: Duration = times.iter().sum();
+    println!(
+        "{}/{} successful, min/avg/max = {:.1}/{:.1}/{:.1} ms",
+        times.len(),
+        count,
+        as_millis(*times.iter().min().unwrap()),
+        as_millis(total) / times.len() as f64,
+        as_millis(*times.iter().max().unwrap())
+    );
+}
+
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
 }
 
 pub fn unlock(mut server: PowServer, nonce: u64) -> () {
     println!("nonce: {}", nonce);
-    nonce_to_bytes(nonce);
     match server.unlock(nonce) {
         Ok(_) => println!("Unlocked"),
         Err(e) => match e {
             PowLockError::Unsuccessful => {
-                println!("Unsuccessful. Hash of base and nonce not less than target.")
+                error!("Unsuccessful. Hash of base and nonce not less than target.")
             }
-            _ => println!("Unknown error"),
+            other => error!("{}", other),
         },
     }
 }
 
+// fetches the lock's challenge, solves it here and unlocks with the solution
+pub fn solve_and_unlock(mut server: PowServer, num_workers: u8, order: NonceByteOrder) -> () {
+    let challenge = match server.challenge() {
+        Ok(challenge) => challenge,
+        Err(e) => {
+            error!("{}", e);
+            return;
+        }
+    };
+    println!("base: {}", String::from_utf8_lossy(&challenge.base));
+    println!("target: {}", challenge.target);
+    let mut hash_farm = HashWorkerFarm::new(challenge.base, challenge.target, num_workers);
+    hash_farm.set_nonce_byte_order(order);
+    let solution = match HashWorkerFarm::solve(Box::from(hash_farm)) {
+        Some(solution) => solution,
+        None => {
+            println!("No solution found");
+            return;
+        }
+    };
+    println!("nonce: {}", solution.nonce);
+    server.set_nonce_byte_order(order);
+    match server.unlock_with_solution(&solution) {
+        Ok(_) => println!("Unlocked"),
+        Err(e) => error!("{}", e),
+    }
+}
+
 pub fn open(mut server: PowServer) -> () {
     match server.open() {
         Ok(_) => println!("Lock opened"),
         Err(e) => match e {
-            PowLockError::InvalidOperationWhenLocked => println!("Lock is locked; cannot open"),
-            _ => println!("Unknown error"),
+            PowLockError::InvalidOperationWhenLocked => error!("Lock is locked; cannot open"),
+            other => error!("{}", other),
         },
     }
 }
 
+// opens the lock, first unlocking it with `nonce` if it's still locked
+pub fn open_with_nonce(mut server: PowServer, nonce: u64) -> () {
+    match unlock_and_open(&mut server, nonce) {
+        Ok(true) => println!("Unlocked\nLock opened"),
+        Ok(false) => println!("Lock opened"),
+        Err(e) => error!("{}", e),
+    }
+}
+
+// whether the lock had to be unlocked first; the error says which step failed
+fn unlock_and_open(server: &mut PowServer, nonce: u64) -> Result<bool, String> {
+    let locked = match server.get_status() {
+        Ok(status) => status == LockStatus::Locked,
+        Err(e) => return Err(format!("Unable to get the lock's status: {}", e)),
+    };
+    if locked {
+        server
+            .unlock(nonce)
+            .map_err(|e| format!("Unable to unlock: {}", e))?;
+    }
+    server
+        .open()
+        .map_err(|e| format!("Unable to open: {}", e))?;
+    Ok(locked)
+}
+
 pub fn base(mut server: PowServer) -> () {
     match server.get_base() {
         Ok(b) => println!("{}", b),
         Err(e) => match e {
             PowLockError::InvalidOperationWhenUnlocked => {
-                println!("Lock is unlocked; there is no base")
+                error!("Lock is unlocked; there is no base")
             }
-            _ => println!("Unknown error"),
+            other => error!("{}", other),
         },
     }
 }
@@ -87,24 +1401,417 @@ pub fn target(mut server: PowServer) -> () {
         Ok(b) => println!("{}", b),
         Err(e) => match e {
             PowLockError::InvalidOperationWhenUnlocked => {
-                println!("Lock is unlocked; there is no target")
+                error!("Lock is unlocked; there is no target")
             }
-            _ => println!("Unknown error"),
+            other => error!("{}", other),
         },
     }
 }
 
-pub fn lock(mut server: PowServer, target: String) -> () {
-    if target.len() != 64 {
-        println!("Targets must be a 64 character hex string representing a SHA 256 hash");
+// refuses targets expected to be solved in less than `min_time` at `hash_rate` H/s
+pub fn check_min_expected_time(
+    target: &Sha256Hash,
+    min_time: Duration,
+    hash_rate: u64,
+) -> Result<(), String> {
+    let expected_time = Duration::from_secs(target.expected_attempts_to_solve() / hash_rate.max(1));
+    match expected_time < min_time {
+        true => Err(format!(
+            "The target is expected to be solved in {} at {} H/s, less than the minimum of {}; use --force to lock anyway",
+            HumanDuration(expected_time),
+            hash_rate,
+            HumanDuration(min_time)
+        )),
+        false => Ok(()),
     }
-    match server.lock(target) {
+}
+
+pub fn lock(mut server: PowServer, target: Sha256Hash) -> () {
+    match server.lock(target.to_string()) {
         Ok(b) => println!("Locked. Base string is:\n{}", b),
         Err(e) => match e {
             PowLockError::InvalidOperationWhenLocked => {
-                println!("Lock is already locked; cannot lock it again")
+                error!("Lock is already locked; cannot lock it again")
             }
-            _ => println!("Unknown error"),
+            other => error!("{}", other),
         },
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        auto_worker_count, base_description, bench_worker_counts, challenge_from_device,
+        check_min_expected_time, csv_record, device_verifier, format_scaling_table,
+        format_solve_estimate, json_record, notification_payload, parse_worker_count,
+        read_base_arg, target_for_measured_rate, unlock_and_open, write_target, TargetFormat,
+        CSV_HEADER,
+    };
+    use pow_key::hash::{
+        HashAlgorithm, HashSolution, HashWorkerFarm, NonceByteOrder, Sha256Hash, Sha256Hasher,
+        SolveGoal,
+    };
+    use pow_key::net::mock::MockLock;
+    use pow_key::net::PowServer;
+    use rustc_serialize::hex::FromHex;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::str::FromStr;
+    use std::time::Duration;
+
+    #[test]
+    fn it_builds_a_notification_for_a_solution() {
+        let solution = HashSolution {
+            nonce: 42,
+            nonce_high: 0,
+            attempts: 43,
+            hash: Sha256Hash::from_str(
+                &"00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+            )
+            .unwrap(),
+        };
+        let (title, body) = notification_payload(&Some(solution), Duration::from_secs(90));
+        assert_eq!(title, "Solve complete");
+        assert!(body.starts_with("Solved with nonce 42 in "));
+    }
+
+    #[test]
+    fn it_builds_a_notification_for_a_failure() {
+        let (title, body) = notification_payload(&None, Duration::from_secs(90));
+        assert_eq!(title, "Solve failed");
+        assert!(body.starts_with("No solution found after "));
+    }
+
+    #[test]
+    fn it_writes_a_solve_as_two_line_csv() {
+        let base = b"hello, \"world\"".to_vec();
+        let target = Sha256Hash::from_str(
+            &"0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+        )
+        .unwrap();
+        let farm = HashWorkerFarm::new(base.clone(), target.clone(), 1);
+        let solution = HashWorkerFarm::solve(Box::from(farm));
+        let record = csv_record(
+            &base,
+            &SolveGoal::Target(target.clone()),
+            &solution,
+            NonceByteOrder::Little,
+            Duration::from_millis(1500),
+        );
+
+        let mut writer = csv::Writer::from_writer(vec![]);
+        writer.write_record(CSV_HEADER).unwrap();
+        writer.write_record(&record).unwrap();
+        let output = writer.into_inner().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output).lines().count(), 2);
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .from_reader(&output[..]);
+        let rows: Vec<Vec<String>> = reader
+            .records()
+            .map(|r| r.unwrap().iter().map(|f| f.to_string()).collect())
+            .collect();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0], CSV_HEADER.to_vec());
+        assert_eq!(rows[1], record);
+        let solution = solution.unwrap();
+        assert_eq!(rows[1][0], "hello, \"world\"");
+        assert_eq!(rows[1][1], target.to_string());
+        assert_eq!(rows[1][2], solution.nonce.to_string());
+        assert_eq!(rows[1][4], solution.hash.to_string());
+        assert_eq!(rows[1][6], "1500");
+    }
+
+    #[test]
+    fn it_writes_the_reason_there_is_no_solution_as_json() {
+        let goal = SolveGoal::Target(
+            Sha256Hash::from_str(
+                &"00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+            )
+            .unwrap(),
+        );
+        let elapsed = Duration::from_millis(1500);
+        assert_eq!(
+            json_record(
+                b"abc",
+                &goal,
+                &None,
+                Some("max_attempts"),
+                NonceByteOrder::Little,
+                elapsed
+            ),
+            r#"{"solution":null,"reason":"max_attempts"}"#
+        );
+        let solution = HashSolution {
+            nonce: 1,
+            nonce_high: 0,
+            attempts: 2,
+            hash: Sha256Hash::from_str(
+                &"00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+            )
+            .unwrap(),
+        };
+        assert_eq!(
+            json_record(
+                b"abc",
+                &goal,
+                &Some(solution),
+                None,
+                NonceByteOrder::Little,
+                elapsed
+            ),
+            r#"{"base":"abc","nonce":1,"nonce_hex":"0100000000000000","hash":"00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff","target":"00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff","attempts":2,"elapsed_secs":1.5}"#
+        );
+    }
+
+    #[test]
+    fn it_describes_the_base_by_its_digest() {
+        let base = b"hello, world".to_vec();
+        let digest = Sha256Hasher::hash_reader(&base[..]).unwrap();
+        assert_eq!(
+            base_description(&base, false),
+            format!("Base string: hello, world,\nBase digest: {}", digest)
+        );
+        assert_eq!(
+            base_description(&base, true),
+            format!("Base digest: {}", digest)
+        );
+        let binary = vec![0xff, 0xfe, 0x00];
+        assert_eq!(
+            base_description(&binary, false),
+            format!(
+                "Base digest: {}",
+                Sha256Hasher::hash_reader(&binary[..]).unwrap()
+            )
+        );
+    }
+
+    #[test]
+    fn it_reads_the_base_from_a_file_or_stdin() {
+        let path = std::env::temp_dir().join(format!("pow_key_base_{}", std::process::id()));
+        let arg = format!("@{}", path.display());
+        std::fs::write(&path, "helloworld").unwrap();
+        assert_eq!(read_base_arg(&arg, &b""[..]).unwrap(), b"helloworld");
+        // only one trailing newline is left off
+        std::fs::write(&path, "helloworld\n\n").unwrap();
+        assert_eq!(read_base_arg(&arg, &b""[..]).unwrap(), b"helloworld\n");
+        std::fs::remove_file(&path).unwrap();
+        assert!(read_base_arg(&arg, &b""[..]).is_err());
+
+        assert_eq!(
+            read_base_arg("-", &b"frompipe\r\n"[..]).unwrap(),
+            b"frompipe"
+        );
+        assert_eq!(
+            read_base_arg("helloworld\n", &b""[..]).unwrap(),
+            b"helloworld\n"
+        );
+    }
+
+    #[test]
+    fn it_formats_a_scaling_table() {
+        assert_eq!(
+            format_scaling_table(&[(1, 1000), (2, 1900), (4, 3000)]),
+            concat!(
+                "Workers          H/s  Speedup\n",
+                "      1         1000    1.00x\n",
+                "      2         1900    1.90x\n",
+                "      4         3000    3.00x\n",
+            )
+        );
+        assert_eq!(bench_worker_counts(6), vec![1, 2, 4, 6]);
+        assert_eq!(bench_worker_counts(8), vec![1, 2, 4, 8]);
+        assert_eq!(bench_worker_counts(1), vec![1]);
+    }
+
+    #[test]
+    fn it_makes_a_target_for_a_measured_rate() {
+        assert_eq!(
+            target_for_measured_rate("4hr 25min", 250_000).unwrap(),
+            Sha256Hash::target_for_duration("4hr 25min".to_string(), 250_000)
+        );
+        assert!(target_for_measured_rate("soon", 250_000).is_err());
+    }
+
+    #[test]
+    fn it_writes_a_target_as_raw_bytes() {
+        let target = Sha256Hash::target_for_difficulty(1);
+        let mut out = vec![];
+        write_target(&mut out, &target, TargetFormat::Bytes).unwrap();
+        assert_eq!(out.len(), 32);
+        assert_eq!(&out[..], &target.as_bytes()[..]);
+    }
+
+    #[test]
+    fn it_formats_a_solve_estimate() {
+        assert_eq!(format_solve_estimate(7_200_000, 1000), "2 hours");
+        assert_eq!(format_solve_estimate(7_200_000, 0), "unknown");
+        assert_eq!(format_solve_estimate(500, 1000), "500 ms");
+    }
+
+    #[test]
+    fn it_resolves_worker_counts_against_the_cores() {
+        assert_eq!(parse_worker_count("50%", 8), Ok(4));
+        assert_eq!(parse_worker_count("0.5x", 8), Ok(4));
+        assert_eq!(parse_worker_count("3", 8), Ok(3));
+        assert!(parse_worker_count("0%", 8).is_err());
+        assert!(parse_worker_count("0", 8).is_err());
+        assert!(parse_worker_count("half", 8).is_err());
+        assert_eq!(parse_worker_count("auto", 8), Ok(8));
+        assert_eq!(auto_worker_count(0), 1);
+        assert_eq!(auto_worker_count(1024), 255);
+    }
+
+    #[test]
+    fn it_only_accepts_solutions_the_device_accepts() {
+        let base = b"helloworld".to_vec();
+        // the device is stricter than the solver, so some candidates are rejected
+        let device_target = Sha256Hash::from_str(
+            &"00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+        )
+        .unwrap();
+        let solver_target = Sha256Hash::from_str(
+            &"0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+        )
+        .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let device_base = base.clone();
+        let accepted_target = device_target.clone();
+        std::thread::spawn(move || {
+            let hasher = Sha256Hasher::new(device_base);
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let line = match line {
+                    Ok(l) => l,
+                    Err(_) => return,
+                };
+                let mut nonce_bytes = [0u8; 8];
+                nonce_bytes.copy_from_slice(&line[1..].from_hex().unwrap());
+                let hash = hasher.hash_with_nonce(u64::from_le_bytes(nonce_bytes));
+                let reply: &[u8] = match hash.meets_target(&accepted_target) {
+                    true => b"1\n",
+                    false => b"0\n",
+                };
+                writer.write_all(reply).unwrap();
+            }
+        });
+
+        let server = PowServer::new("127.0.0.1".to_string(), port.to_string());
+        let farm = HashWorkerFarm::new(base, solver_target, 1);
+        let solution = HashWorkerFarm::solve_verified(
+            Box::from(farm),
+            device_verifier(server, NonceByteOrder::Little),
+        )
+        .unwrap();
+        assert!(solution.hash < device_target);
+    }
+
+    #[test]
+    fn it_solves_the_challenge_fetched_from_the_device() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                let reply: &[u8] = match line.unwrap().as_str() {
+                    "b" => b"devicebase\n",
+                    "t" => b"0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff\n",
+                    _ => return,
+                };
+                writer.write_all(reply).unwrap();
+            }
+        });
+
+        let mut server = PowServer::new("127.0.0.1".to_string(), port.to_string());
+        let (base, goal) = challenge_from_device(&mut server).unwrap();
+        assert_eq!(base, b"devicebase".to_vec());
+        let target = match goal {
+            SolveGoal::Target(target) => target,
+            _ => panic!("expected a target"),
+        };
+        assert_eq!(
+            target.to_string(),
+            "0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"
+        );
+        let farm = HashWorkerFarm::new(base, target.clone(), 1);
+        let solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        assert_eq!(
+            Sha256Hasher::new(b"devicebase".to_vec()).hash_with_nonce(solution.nonce),
+            solution.hash
+        );
+        assert!(solution.hash.meets_target(&target));
+    }
+
+    #[test]
+    fn it_unlocks_a_locked_lock_before_opening_it() {
+        let lock = MockLock::bind("127.0.0.1:0").unwrap();
+        let port = lock.local_addr().unwrap().port();
+        std::thread::spawn(move || lock.run());
+        let mut server = PowServer::new("127.0.0.1".to_string(), port.to_string());
+        let target = Sha256Hash::from_str(
+            &"0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+        )
+        .unwrap();
+        let base = server.lock(target.to_string()).unwrap();
+        let hasher = Sha256Hasher::new(base.into_bytes());
+        let wrong_nonce = (0..).find(|&n| !hasher.verify(n, &target)).unwrap();
+        assert!(unlock_and_open(&mut server, wrong_nonce)
+            .unwrap_err()
+            .starts_with("Unable to unlock: "));
+
+        let nonce = (0..).find(|&n| hasher.verify(n, &target)).unwrap();
+        assert_eq!(unlock_and_open(&mut server, nonce), Ok(true));
+        // already unlocked, so it's only opened
+        assert_eq!(unlock_and_open(&mut server, nonce), Ok(false));
+    }
+
+    #[test]
+    fn it_rejects_a_lock_target_easier_than_the_minimum() {
+        let min_time = Duration::from_secs(10 * 60);
+        // 2^20 expected attempts at 1000 H/s is about 17 minutes
+        let target = Sha256Hash::from_str(
+            &"00000fffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+        )
+        .unwrap();
+        assert!(check_min_expected_time(&target, min_time, 1000).is_ok());
+        assert!(check_min_expected_time(&target, min_time, 10_000).is_err());
+    }
+
+    #[test]
+    fn it_verifies_a_batch_of_nonces() {
+        let max = "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        let min = "0000000000000000000000000000000000000000000000000000000000000001";
+        let jsonl = format!(
+            "{{\"base\":\"helloworld\",\"nonce\":0,\"target\":\"{}\"}}\n\n{{\"base\":\"helloworld\",\"nonce\":0,\"target\":\"{}\"}}\n",
+            max, min
+        );
+        let csv = format!(
+            "base,nonce,target\nhelloworld,0,{}\nhelloworld,0,{}\n",
+            max, min
+        );
+        for contents in [jsonl, csv].iter() {
+            let mut output = vec![];
+            assert_eq!(
+                super::verify_batch(contents, HashAlgorithm::Sha256, &mut output),
+                Ok(false)
+            );
+            let output = String::from_utf8(output).unwrap();
+            let lines: Vec<&str> = output.lines().collect();
+            assert_eq!(lines.len(), 3);
+            assert!(lines[0].starts_with("1: valid, nonce 0 hashes to "));
+            assert!(lines[1].starts_with("2: invalid, nonce 0 hashes to "));
+            assert_eq!(lines[2], "1 of 2 valid");
+        }
+        assert!(super::verify_batch(
+            "base,nonce,target\nhelloworld,x,00\n",
+            HashAlgorithm::Sha256,
+            vec![]
+        )
+        .is_err());
+    }
+}
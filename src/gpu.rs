@@ -0,0 +1,310 @@
+// searches nonces on a GPU through OpenCL, when built with the "opencl"
+// feature. Only sha256(base || nonce) against a numeric target is supported
+#[cfg(feature = "opencl")]
+use crate::hash::{HashSolution, Sha256Hasher, SolveGoal};
+use crate::hash::{Nonce, SolveOutcome};
+use std::sync::atomic::AtomicBool;
+#[cfg(feature = "opencl")]
+use std::sync::atomic::Ordering;
+
+// nonces hashed per kernel launch
+pub const DEFAULT_BATCH_SIZE: u64 = 1 << 22;
+
+// the kernel hashes the message in a fixed size buffer, which limits the base
+pub const MAX_MESSAGE_BLOCKS: usize = 8;
+
+// splits [start, end) into kernel launches of at most `size` nonces each,
+// yielding the first nonce and the number of nonces in each launch
+pub struct NonceBatches {
+    next: Nonce,
+    end: Nonce, // not inclusive
+    size: u64,
+}
+
+impl NonceBatches {
+    pub fn new(start: Nonce, end: Nonce, size: u64) -> NonceBatches {
+        NonceBatches {
+            next: start,
+            end: end,
+            size: size,
+        }
+    }
+}
+
+impl Iterator for NonceBatches {
+    type Item = (Nonce, u64);
+
+    fn next(&mut self) -> Option<(Nonce, u64)> {
+        if self.next >= self.end {
+            return None;
+        }
+        let count = (self.end - self.next).min(self.size);
+        let first = self.next;
+        self.next += count;
+        Some((first, count))
+    }
+}
+
+// pads base || nonce the way SHA-256 does, with zeros where the nonce goes;
+// the kernel writes each nonce at `base.len()` and hashes the blocks as is
+pub fn message_template(base: &[u8]) -> Result<Vec<u8>, String> {
+    let message_len = base.len() + 8;
+    let padded_len = (message_len + 9).div_ceil(64) * 64;
+    if padded_len > MAX_MESSAGE_BLOCKS * 64 {
+        return Err(format!(
+            "The base is too long to solve on the GPU; the limit is {} bytes",
+            MAX_MESSAGE_BLOCKS * 64 - 17
+        ));
+    }
+    let mut template = base.to_vec();
+    template.resize(message_len, 0);
+    template.push(0x80);
+    template.resize(padded_len - 8, 0);
+    template.extend_from_slice(&((message_len as u64) * 8).to_be_bytes());
+    Ok(template)
+}
+
+#[cfg(feature = "opencl")]
+const KERNEL: &str = r#"
+#pragma OPENCL EXTENSION cl_khr_int64_base_atomics : enable
+
+#define MAX_MESSAGE_BLOCKS 8
+#define ROTR(x, n) rotate((x), (uint)(32 - (n)))
+
+__constant uint K[64] = {
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2
+};
+
+void compress(uint *h, const uchar *block) {
+    uint w[64];
+    for (int i = 0; i < 16; i++) {
+        w[i] = (uint)block[4 * i] << 24 | (uint)block[4 * i + 1] << 16
+            | (uint)block[4 * i + 2] << 8 | (uint)block[4 * i + 3];
+    }
+    for (int i = 16; i < 64; i++) {
+        uint s0 = ROTR(w[i - 15], 7) ^ ROTR(w[i - 15], 18) ^ (w[i - 15] >> 3);
+        uint s1 = ROTR(w[i - 2], 17) ^ ROTR(w[i - 2], 19) ^ (w[i - 2] >> 10);
+        w[i] = w[i - 16] + s0 + w[i - 7] + s1;
+    }
+    uint a = h[0], b = h[1], c = h[2], d = h[3], e = h[4], f = h[5], g = h[6], hh = h[7];
+    for (int i = 0; i < 64; i++) {
+        uint t1 = hh + (ROTR(e, 6) ^ ROTR(e, 11) ^ ROTR(e, 25)) + ((e & f) ^ (~e & g)) + K[i] + w[i];
+        uint t2 = (ROTR(a, 2) ^ ROTR(a, 13) ^ ROTR(a, 22)) + ((a & b) ^ (a & c) ^ (b & c));
+        hh = g; g = f; f = e; e = d + t1; d = c; c = b; b = a; a = t1 + t2;
+    }
+    h[0] += a; h[1] += b; h[2] += c; h[3] += d; h[4] += e; h[5] += f; h[6] += g; h[7] += hh;
+}
+
+// records the lowest nonce in the launch whose hash is below the target
+__kernel void search(
+    __global const uchar *template,
+    uint template_len,
+    uint nonce_offset,
+    __global const uchar *target,
+    ulong first_nonce,
+    ulong count,
+    __global ulong *result
+) {
+    ulong id = get_global_id(0);
+    if (id >= count) {
+        return;
+    }
+    ulong nonce = first_nonce + id;
+    uchar message[MAX_MESSAGE_BLOCKS * 64];
+    for (uint i = 0; i < template_len; i++) {
+        message[i] = template[i];
+    }
+    for (int i = 0; i < 8; i++) {
+        message[nonce_offset + i] = (uchar)(nonce >> (8 * i));
+    }
+    uint h[8] = {
+        0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+        0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19
+    };
+    for (uint block = 0; block < template_len; block += 64) {
+        compress(h, message + block);
+    }
+    for (int i = 0; i < 32; i++) {
+        uchar digest_byte = (uchar)(h[i / 4] >> (24 - 8 * (i % 4)));
+        if (digest_byte < target[i]) {
+            atom_min(result, nonce);
+            return;
+        }
+        if (digest_byte > target[i]) {
+            return;
+        }
+    }
+}
+"#;
+
+// searches nonces from 0 upward, giving up after `max_attempts` or once
+// `interrupt` is set between launches; fails if there's no usable GPU, so the
+// caller can fall back to the CPU
+#[cfg(feature = "opencl")]
+pub fn solve(
+    base: &[u8],
+    goal: &SolveGoal,
+    max_attempts: Option<u64>,
+    batch_size: u64,
+    interrupt: Option<&AtomicBool>,
+) -> Result<SolveOutcome, String> {
+    use ocl::{Buffer, ProQue};
+
+    let target = match goal {
+        SolveGoal::Target(target) => target.value,
+        SolveGoal::Prefix(_) => return Err("The GPU only solves for a target".to_string()),
+    };
+    let template = message_template(base)?;
+    let pro_que = ProQue::builder()
+        .src(KERNEL)
+        .dims(batch_size as usize)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let template_buffer = Buffer::<u8>::builder()
+        .queue(pro_que.queue().clone())
+        .len(template.len())
+        .copy_host_slice(&template)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let target_buffer = Buffer::<u8>::builder()
+        .queue(pro_que.queue().clone())
+        .len(target.len())
+        .copy_host_slice(&target)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let result_buffer = Buffer::<u64>::builder()
+        .queue(pro_que.queue().clone())
+        .len(1)
+        .fill_val(std::u64::MAX)
+        .build()
+        .map_err(|e| e.to_string())?;
+    let kernel = pro_que
+        .kernel_builder("search")
+        .arg(&template_buffer)
+        .arg(template.len() as u32)
+        .arg(base.len() as u32)
+        .arg(&target_buffer)
+        .arg(0u64)
+        .arg(0u64)
+        .arg(&result_buffer)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let hasher = Sha256Hasher::new(base.to_vec());
+    let end = max_attempts.unwrap_or(std::u64::MAX);
+    for (first_nonce, count) in NonceBatches::new(0, end, batch_size) {
+        if interrupt.is_some_and(|flag| flag.load(Ordering::SeqCst)) {
+            return Ok(SolveOutcome::Interrupted {
+                attempts: first_nonce,
+                highest_nonce: first_nonce,
+            });
+        }
+        kernel
+            .set_arg(4, first_nonce)
+            .and_then(|_| kernel.set_arg(5, count))
+            .map_err(|e| e.to_string())?;
+        unsafe {
+            kernel.enq().map_err(|e| e.to_string())?;
+        }
+        let mut result = vec![0u64; 1];
+        result_buffer
+            .read(&mut result)
+            .enq()
+            .map_err(|e| e.to_string())?;
+        if result[0] != std::u64::MAX {
+            let nonce = result[0];
+            let hash = hasher.hash_with_nonce(nonce);
+            // the kernel is checked against the CPU rather than trusted
+            if !goal.is_met_by(&hash) {
+                return Err(format!(
+                    "The GPU reported nonce {}, which doesn't solve the challenge",
+                    nonce
+                ));
+            }
+            return Ok(SolveOutcome::Solved(HashSolution {
+                nonce: nonce,
+                nonce_high: 0,
+                attempts: nonce + 1,
+                hash: hash,
+            }));
+        }
+    }
+    match max_attempts {
+        Some(_) => Ok(SolveOutcome::MaxAttempts),
+        None => Ok(SolveOutcome::Exhausted),
+    }
+}
+
+#[cfg(not(feature = "opencl"))]
+pub fn solve(
+    _base: &[u8],
+    _goal: &crate::hash::SolveGoal,
+    _max_attempts: Option<u64>,
+    _batch_size: u64,
+    _interrupt: Option<&AtomicBool>,
+) -> Result<SolveOutcome, String> {
+    Err("Solving on the GPU requires building with the \"opencl\" feature".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{message_template, NonceBatches};
+
+    #[test]
+    fn it_splits_a_range_into_batches() {
+        let batches: Vec<(u64, u64)> = NonceBatches::new(0, 10, 4).collect();
+        assert_eq!(batches, vec![(0, 4), (4, 4), (8, 2)]);
+        let batches: Vec<(u64, u64)> =
+            NonceBatches::new(std::u64::MAX - 5, std::u64::MAX, 4).collect();
+        assert_eq!(
+            batches,
+            vec![(std::u64::MAX - 5, 4), (std::u64::MAX - 1, 1)]
+        );
+        assert_eq!(NonceBatches::new(5, 5, 4).count(), 0);
+    }
+
+    #[test]
+    fn it_pads_the_message_like_sha256() {
+        let template = message_template(b"abc").unwrap();
+        assert_eq!(template.len(), 64);
+        assert_eq!(&template[..3], b"abc");
+        assert_eq!(template[11], 0x80);
+        assert_eq!(template[63], 88); // bits in base || nonce
+        assert_eq!(message_template(&[0; 48]).unwrap().len(), 128);
+        assert!(message_template(&[0; 500]).is_err());
+    }
+
+    #[cfg(feature = "opencl")]
+    #[test]
+    fn it_finds_the_same_solution_as_the_cpu() {
+        use crate::hash::{HashWorkerFarm, Sha256Hash, SolveGoal};
+        use std::str::FromStr;
+
+        let base = b"helloworld".to_vec();
+        let goal = SolveGoal::Target(
+            Sha256Hash::from_str(
+                &"000fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+            )
+            .unwrap(),
+        );
+        let gpu_solution = match super::solve(&base, &goal, None, 1 << 16, None) {
+            Ok(outcome) => outcome.solution().unwrap(),
+            Err(e) => {
+                eprintln!("Skipping the GPU check: {}", e);
+                return;
+            }
+        };
+        // a single worker searches from 0 upward, so it finds the lowest solving nonce too
+        let farm = HashWorkerFarm::new_with_goal(base, goal, 1);
+        let cpu_solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        assert_eq!(gpu_solution.nonce, cpu_solution.nonce);
+        assert_eq!(gpu_solution.hash, cpu_solution.hash);
+    }
+}
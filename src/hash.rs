@@ -1,14 +1,27 @@
 use rustc_serialize as serialize;
 
+use self::serialize::base64::{ToBase64, STANDARD};
 use self::serialize::hex::{FromHex, ToHex};
-use byteorder::{LittleEndian, WriteBytesExt};
+use crate::ranges::NonceRanges;
+use crate::state::SolveState;
+use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
 use crypto::digest::Digest;
 use crypto::sha2::Sha256;
-use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressStyle};
+use indicatif::{HumanDuration, MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use log::{debug, warn};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::io::{IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
 use std::time::Duration;
 use std::time::Instant;
+use std::time::{SystemTime, UNIX_EPOCH};
 use uint::U256;
 
 // BASE: string
@@ -17,24 +30,163 @@ use uint::U256;
 
 pub type Nonce = u64;
 
+// a nonce in the 128-bit space, see NonceWidth
+pub type WideNonce = u128;
+
+// how many bytes of nonce are appended to the base; 128 bits gives each
+// worker a whole 64-bit range to itself and room for targets so hard a 64-bit
+// nonce may not solve them
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonceWidth {
+    Bits64,
+    Bits128,
+}
+
+impl FromStr for NonceWidth {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "64" => Ok(NonceWidth::Bits64),
+            "128" => Ok(NonceWidth::Bits128),
+            _ => Err(format!("Unknown nonce width {}; expected 64 or 128", s)),
+        }
+    }
+}
+
+// the order of the nonce's bytes when hashed and sent to the lock; most
+// firmware builds expect little-endian, but some are built big-endian
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum NonceByteOrder {
+    #[default]
+    Little,
+    Big,
+}
+
 pub trait TNonce {
-    fn as_hex_bytes(&self) -> String;
+    fn as_hex_bytes(&self, order: NonceByteOrder) -> String;
 }
 
 impl TNonce for u64 {
-    fn as_hex_bytes(&self) -> String {
-        nonce_to_bytes(*self).to_hex()
+    fn as_hex_bytes(&self, order: NonceByteOrder) -> String {
+        nonce_to_bytes(*self, order).to_hex()
+    }
+}
+
+// the ways a device might combine the base and nonce into a hash
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HashAlgorithm {
+    Sha256,                 // sha256(base || nonce)
+    DoubleSha256,           // sha256(sha256(base || nonce))
+    Sha256NonceFirst,       // sha256(nonce || base)
+    DoubleSha256NonceFirst, // sha256(sha256(nonce || base))
+}
+
+impl HashAlgorithm {
+    pub const ALL: [HashAlgorithm; 4] = [
+        HashAlgorithm::Sha256,
+        HashAlgorithm::DoubleSha256,
+        HashAlgorithm::Sha256NonceFirst,
+        HashAlgorithm::DoubleSha256NonceFirst,
+    ];
+
+    fn passes(self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Sha256NonceFirst => 1,
+            HashAlgorithm::DoubleSha256 | HashAlgorithm::DoubleSha256NonceFirst => 2,
+        }
+    }
+
+    fn nonce_first(self) -> bool {
+        match self {
+            HashAlgorithm::Sha256NonceFirst | HashAlgorithm::DoubleSha256NonceFirst => true,
+            HashAlgorithm::Sha256 | HashAlgorithm::DoubleSha256 => false,
+        }
+    }
+
+    // hashes an already assembled message with as many passes as the algorithm uses
+    pub fn hash(self, message: &[u8]) -> Sha256Hash {
+        let mut hash = Sha256Hasher::hash_impl(message);
+        for _ in 1..self.passes() {
+            hash = Sha256Hasher::hash_impl(&hash.value);
+        }
+        hash
+    }
+}
+
+impl FromStr for HashAlgorithm {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        HashAlgorithm::ALL
+            .iter()
+            .find(|algorithm| algorithm.to_string() == s)
+            .cloned()
+            .ok_or_else(|| format!("Unknown hash algorithm {}", s))
+    }
+}
+
+impl std::fmt::Display for HashAlgorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::DoubleSha256 => "sha256d",
+            HashAlgorithm::Sha256NonceFirst => "sha256-nonce-first",
+            HashAlgorithm::DoubleSha256NonceFirst => "sha256d-nonce-first",
+        };
+        write!(f, "{}", name)
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Sha256Hasher {
     base: Vec<u8>,
+    algorithm: HashAlgorithm,
+    nonce_width: NonceWidth,
+    nonce_high: u64, // the upper 64 bits of every nonce hashed, with 128-bit nonces
+    nonce_byte_order: NonceByteOrder,
 }
 
 impl Sha256Hasher {
     pub fn new(base: Vec<u8>) -> Sha256Hasher {
-        Sha256Hasher { base: base }
+        Sha256Hasher::with_algorithm(base, HashAlgorithm::Sha256)
+    }
+
+    pub fn with_algorithm(base: Vec<u8>, algorithm: HashAlgorithm) -> Sha256Hasher {
+        Sha256Hasher {
+            base: base,
+            algorithm: algorithm,
+            nonce_width: NonceWidth::Bits64,
+            nonce_high: 0,
+            nonce_byte_order: NonceByteOrder::Little,
+        }
+    }
+
+    pub fn set_nonce_width(&mut self, width: NonceWidth) -> () {
+        self.nonce_width = width;
+    }
+
+    pub fn set_nonce_byte_order(&mut self, order: NonceByteOrder) -> () {
+        self.nonce_byte_order = order;
+    }
+
+    // hashes everything the reader produces, a chunk at a time, so large
+    // files don't have to be held in memory
+    pub fn hash_reader<R: Read>(mut reader: R) -> std::io::Result<Sha256Hash> {
+        let mut sha = Sha256::new();
+        let mut buffer = [0u8; 64 * 1024];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            sha.input(&buffer[..read]);
+        }
+        let mut result = [0x00; 32];
+        sha.result(&mut result);
+        Ok(Sha256Hash { value: result })
+    }
+
+    pub fn hash_bytes(bytes: &[u8]) -> Sha256Hash {
+        Sha256Hasher::hash_impl(bytes)
     }
 
     fn hash_impl(base: &[u8]) -> Sha256Hash {
@@ -46,30 +198,103 @@ impl Sha256Hasher {
     }
 
     pub fn hash_with_nonce(&self, nonce: Nonce) -> Sha256Hash {
+        self.hash_with_wide_nonce((self.nonce_high as WideNonce) << 64 | nonce as WideNonce)
+    }
+
+    // whether the nonce solves for `target`, the way the lock checks an unlock
+    pub fn verify(&self, nonce: Nonce, target: &Sha256Hash) -> bool {
+        self.hash_with_nonce(nonce).meets_target(target)
+    }
+
+    // each nonce in `range` with its hash, hashed only as the iterator is
+    // advanced, for analysis or custom solvers without the worker farm
+    pub fn hash_range(
+        &self,
+        range: std::ops::Range<Nonce>,
+    ) -> impl Iterator<Item = (Nonce, Sha256Hash)> + '_ {
+        range.map(move |nonce| (nonce, self.hash_with_nonce(nonce)))
+    }
+
+    // the nonce is written in as many bytes as the hasher's nonce width; a
+    // 64-bit width only uses the lower 64 bits
+    pub fn hash_with_wide_nonce(&self, nonce: WideNonce) -> Sha256Hash {
         let mut cat = vec![];
-        cat.extend_from_slice(&self.base);
-        let x = nonce_to_bytes(nonce);
-        cat.extend_from_slice(&x);
-        Sha256Hasher::hash_impl(cat.as_slice())
+        let x = nonce_to_bytes_with_width(nonce, self.nonce_width, self.nonce_byte_order);
+        match self.algorithm.nonce_first() {
+            true => {
+                cat.extend_from_slice(&x);
+                cat.extend_from_slice(&self.base);
+            }
+            false => {
+                cat.extend_from_slice(&self.base);
+                cat.extend_from_slice(&x);
+            }
+        }
+        self.algorithm.hash(&cat)
     }
 }
 
-#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone)]
+// serialized as its hex string. Not behind a serde feature: solve state
+// (state.rs, ranges.rs) is saved as JSON, so serde is always a dependency
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
 pub struct Sha256Hash {
     pub value: [u8; 32],
 }
 
+impl Sha256Hash {
+    pub fn from_bytes(bytes: [u8; 32]) -> Sha256Hash {
+        Sha256Hash { value: bytes }
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.value
+    }
+
+    // standard base64 with padding, for tools that take the target that way
+    pub fn to_base64(&self) -> String {
+        self.value.to_base64(STANDARD)
+    }
+}
+
 impl std::fmt::Display for Sha256Hash {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(f, "{}", self.value.to_hex())
     }
 }
 
+// why a string isn't a hash
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseHashError {
+    WrongLength { expected: usize, got: usize }, // in hex characters, or bytes for a slice
+    InvalidHex(String),
+}
+
+impl std::fmt::Display for ParseHashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            // a hash is 64 hex characters or 32 bytes
+            ParseHashError::WrongLength { expected: 32, got } => {
+                write!(f, "Input must be 32 bytes, not {}", got)
+            }
+            ParseHashError::WrongLength { expected, got } => {
+                write!(f, "Input must be {} characters, not {}", expected, got)
+            }
+            ParseHashError::InvalidHex(e) => write!(f, "Invalid hex: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ParseHashError {}
+
 impl FromStr for Sha256Hash {
-    type Err = String;
+    type Err = ParseHashError;
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         if s.len() != 64 {
-            return Err("Input must be 64 characters".to_string());
+            return Err(ParseHashError::WrongLength {
+                expected: 64,
+                got: s.len(),
+            });
         }
         let mut result: [u8; 32] = [0; 32];
         match s.from_hex() {
@@ -79,13 +304,51 @@ impl FromStr for Sha256Hash {
                 }
                 Ok(Sha256Hash { value: result })
             }
-            Err(e) => Err(format!("Serialization failed: {:?}", e)),
+            Err(e) => Err(ParseHashError::InvalidHex(e.to_string())),
+        }
+    }
+}
+
+impl TryFrom<String> for Sha256Hash {
+    type Error = ParseHashError;
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Sha256Hash::from_str(&s)
+    }
+}
+
+impl From<[u8; 32]> for Sha256Hash {
+    fn from(bytes: [u8; 32]) -> Self {
+        Sha256Hash::from_bytes(bytes)
+    }
+}
+
+impl TryFrom<&[u8]> for Sha256Hash {
+    type Error = ParseHashError;
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.len() != 32 {
+            return Err(ParseHashError::WrongLength {
+                expected: 32,
+                got: bytes.len(),
+            });
         }
+        let mut value = [0u8; 32];
+        value.copy_from_slice(bytes);
+        Ok(Sha256Hash { value: value })
+    }
+}
+
+impl From<Sha256Hash> for String {
+    fn from(hash: Sha256Hash) -> String {
+        hash.to_string()
     }
 }
 
+// the easiest target in bitcoin, which by definition has a difficulty of 1
+pub const DIFFICULTY_1_TARGET: &str =
+    "00000000ffff0000000000000000000000000000000000000000000000000000";
+
 impl Sha256Hash {
-    fn target_for_hash_attempts_expected(hash_attempts_expected: u64) -> Self {
+    pub(crate) fn target_for_hash_attempts_expected(hash_attempts_expected: u64) -> Self {
         // see discussion on geometic distribution here:
         // https://en.wikipedia.org/wiki/Geometric_distribution
         let max_attempts = U256::from_str(
@@ -98,20 +361,80 @@ impl Sha256Hash {
         Sha256Hash { value: result }
     }
 
+    pub fn target_for_difficulty(difficulty: u64) -> Self {
+        let difficulty_1_target = U256::from_str(DIFFICULTY_1_TARGET).unwrap();
+        let target_u256 = difficulty_1_target / U256::from(difficulty);
+        let mut result: [u8; 32] = [0; 32];
+        target_u256.to_big_endian(&mut result);
+        Sha256Hash { value: result }
+    }
+
+    // the inverse of `target_for_difficulty`: how many times harder this
+    // target is than the difficulty 1 target. Rounds down, so anything easier
+    // than difficulty 1 is 0, and saturates for the very hardest targets
+    pub fn get_difficulty(&self) -> u64 {
+        let target = self.to_u256();
+        if target.is_zero() {
+            return std::u64::MAX;
+        }
+        saturating_u64(U256::from_str(DIFFICULTY_1_TARGET).unwrap() / target)
+    }
+
     pub fn target_for_duration(duration: String, hash_rate: u64 /* hashes/s */) -> Self {
         let d: Duration = duration.parse::<humantime::Duration>().unwrap().into();
         let expected_hashes: u64 = d.as_secs() as u64 * hash_rate;
-        println!("Expected hashes: {}", expected_hashes);
+        debug!("Expected hashes: {}", expected_hashes);
         Sha256Hash::target_for_hash_attempts_expected(expected_hashes)
     }
 
+    pub fn to_u256(&self) -> U256 {
+        U256::from(self.value)
+    }
+
+    // whether the hash solves for `target`: it must be strictly less, the
+    // way the lock checks an unlock
+    pub fn meets_target(&self, target: &Sha256Hash) -> bool {
+        self < target
+    }
+
+    // how far the hash falls below `target`, if it meets it at all
+    pub fn margin_below(&self, target: &Sha256Hash) -> Option<U256> {
+        match self.meets_target(target) {
+            true => Some(target.to_u256() - self.to_u256()),
+            false => None,
+        }
+    }
+
+    // the margin as a fraction of the target; near 0 means the hash only
+    // just met it
+    pub fn margin_ratio(&self, target: &Sha256Hash) -> Option<f64> {
+        self.margin_below(target)
+            .map(|margin| u256_as_f64(margin) / u256_as_f64(target.to_u256()))
+    }
+
+    // counts from the most significant bit of the big-endian value
+    pub fn leading_zero_bits(&self) -> u32 {
+        let mut bits = 0;
+        for byte in self.value.iter() {
+            bits += byte.leading_zeros();
+            if *byte != 0 {
+                break;
+            }
+        }
+        bits
+    }
+
     pub fn expected_attempts_to_solve(&self) -> u64 {
         let max_attempts = U256::from_str(
             &"ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
         )
         .unwrap();
-        let target_u256 = U256::from(self.value);
-        (max_attempts / target_u256).as_u64()
+        let target_u256 = self.to_u256();
+        // a zero target can't be met at all
+        match target_u256.is_zero() {
+            true => std::u64::MAX,
+            false => saturating_u64(max_attempts / target_u256),
+        }
     }
 
     /**
@@ -120,7 +443,7 @@ impl Sha256Hash {
     pub fn p90_attempts_to_solve(&self) -> u64 {
         let expected = self.expected_attempts_to_solve();
         let std_dev = self.standard_deviation_for_expected_attempts();
-        (expected + (1.28 * std_dev as f64) as u64)
+        expected.saturating_add((1.28 * std_dev as f64) as u64)
     }
 
     /**
@@ -129,125 +452,898 @@ impl Sha256Hash {
     pub fn p99_attempts_to_solve(&self) -> u64 {
         let expected = self.expected_attempts_to_solve();
         let std_dev = self.standard_deviation_for_expected_attempts();
-        (expected + (2.33 * std_dev as f64) as u64)
+        expected.saturating_add((2.33 * std_dev as f64) as u64)
+    }
+
+    // the chance a solve succeeds within `attempts`, 1 - (1 - p)^attempts for
+    // a success chance p per attempt, computed so a tiny p isn't rounded away
+    pub fn solve_probability(&self, attempts: u64) -> f64 {
+        if attempts == 0 {
+            return 0.0;
+        }
+        let p = 1.0 / self.expected_attempts_to_solve() as f64;
+        -(attempts as f64 * (-p).ln_1p()).exp_m1()
     }
 
     fn standard_deviation_for_expected_attempts(&self) -> u64 {
-        let max_attempts = U256::from_str(
-            &"ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
-        )
-        .unwrap();
-        let target_u256 = U256::from(self.value);
-        let p_inv = max_attempts / target_u256;
-        let p = 1.0 / p_inv.as_u64() as f64;
+        let p = 1.0 / self.expected_attempts_to_solve() as f64;
         let variance = (1.0 - p) / (p * p);
         let std_dev = variance.sqrt();
         std_dev as u64
     }
 }
 
+// targets so hard more than u64::MAX attempts are expected are reported as u64::MAX
+fn saturating_u64(value: U256) -> u64 {
+    match value > U256::from(std::u64::MAX) {
+        true => std::u64::MAX,
+        false => value.as_u64(),
+    }
+}
+
+fn u256_as_f64(value: U256) -> f64 {
+    let mut bytes = [0u8; 32];
+    value.to_big_endian(&mut bytes);
+    bytes
+        .iter()
+        .fold(0.0, |acc, &byte| acc * 256.0 + byte as f64)
+}
+
+// the condition a hash must meet for its nonce to be a solution
+#[derive(Debug, Clone)]
+pub enum SolveGoal {
+    Target(Sha256Hash), // hash numerically less than the target
+    Prefix(Vec<u8>),    // hash starts with these bytes
+}
+
+impl SolveGoal {
+    pub fn prefix_from_hex(prefix: &str) -> Result<SolveGoal, String> {
+        match prefix.from_hex() {
+            Ok(ref r) if r.len() > 32 => Err("Prefix must be at most 32 bytes".to_string()),
+            Ok(r) => Ok(SolveGoal::Prefix(r)),
+            Err(e) => Err(format!("Serialization failed: {:?}", e)),
+        }
+    }
+
+    pub fn is_met_by(&self, hash: &Sha256Hash) -> bool {
+        match self {
+            SolveGoal::Target(target) => hash.meets_target(target),
+            SolveGoal::Prefix(prefix) => hash.value.starts_with(prefix),
+        }
+    }
+
+    // a numeric target that takes the same number of attempts to solve on
+    // average, used for progress estimates
+    pub fn equivalent_target(&self) -> Sha256Hash {
+        match self {
+            SolveGoal::Target(target) => target.clone(),
+            SolveGoal::Prefix(prefix) => {
+                let mut result: [u8; 32] = [0xff; 32];
+                for byte in result.iter_mut().take(prefix.len()) {
+                    *byte = 0x00;
+                }
+                Sha256Hash { value: result }
+            }
+        }
+    }
+}
+
+// parses the display form of a goal, so a goal survives a round trip through a string
+impl FromStr for SolveGoal {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.starts_with("starts with ") {
+            true => SolveGoal::prefix_from_hex(&s["starts with ".len()..]),
+            false => Sha256Hash::from_str(s)
+                .map(SolveGoal::Target)
+                .map_err(|e| e.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for SolveGoal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SolveGoal::Target(target) => write!(f, "{}", target),
+            SolveGoal::Prefix(prefix) => write!(f, "starts with {}", prefix.to_hex()),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
 pub struct HashSolution {
     pub nonce: Nonce,
-    pub attempts: u64, // hash attempts conducted to find solution
+    pub nonce_high: u64, // the upper 64 bits of a 128-bit nonce, otherwise 0
+    pub attempts: u64,   // hash attempts conducted to find solution
     pub hash: Sha256Hash,
 }
 
+impl HashSolution {
+    // the whole nonce, for solutions found with 128-bit nonces
+    pub fn wide_nonce(&self) -> WideNonce {
+        (self.nonce_high as WideNonce) << 64 | self.nonce as WideNonce
+    }
+
+    // displays the solution with its nonce's bytes in the given order
+    pub fn display_with_order(&self, order: NonceByteOrder) -> SolutionDisplay<'_> {
+        SolutionDisplay {
+            solution: self,
+            order,
+        }
+    }
+}
+
+impl std::fmt::Display for HashSolution {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.display_with_order(NonceByteOrder::default()))
+    }
+}
+
+// a solution as printed by solve, with the nonce bytes in the lock's order
+pub struct SolutionDisplay<'a> {
+    solution: &'a HashSolution,
+    order: NonceByteOrder,
+}
+
+impl<'a> std::fmt::Display for SolutionDisplay<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Solved with nonce: {},\nAs bytes: {},\nHash: {}\nLeading zero bits: {}\nAttempts: {}",
+            self.solution.nonce,
+            self.solution.nonce.as_hex_bytes(self.order),
+            self.solution.hash,
+            self.solution.hash.leading_zero_bits(),
+            self.solution.attempts
+        )
+    }
+}
+
+// how a solve is going, reported on every progress tick
+#[derive(Debug, Clone)]
+pub struct Progress {
+    pub attempts: u64,
+    pub elapsed: Duration,
+    pub hash_rate: f64,           // estimated, in H/s
+    pub best: Option<Sha256Hash>, // the lowest hash seen so far
+}
+
+// why a solve stopped
+pub enum SolveOutcome {
+    Solved(HashSolution),
+    Exhausted,   // every nonce was searched
+    MaxAttempts, // the attempt limit was reached
+    Watchdog,    // abandoned as projected to take too long, see set_give_up_if_slower_than
+    Timeout,     // the deadline passed, see set_deadline
+    // stopped by the interrupt flag, ex: on Ctrl-C, see set_interrupt
+    Interrupted {
+        attempts: u64,
+        highest_nonce: Nonce, // the furthest any worker got
+    },
+}
+
+impl SolveOutcome {
+    pub fn solution(self) -> Option<HashSolution> {
+        match self {
+            SolveOutcome::Solved(solution) => Some(solution),
+            _ => None,
+        }
+    }
+
+    // a stable name for why there's no solution, for machine-readable output
+    pub fn reason(&self) -> Option<&'static str> {
+        match self {
+            SolveOutcome::Solved(_) => None,
+            SolveOutcome::Exhausted => Some("exhausted"),
+            SolveOutcome::MaxAttempts => Some("max_attempts"),
+            SolveOutcome::Watchdog => Some("watchdog"),
+            SolveOutcome::Timeout => Some("timeout"),
+            SolveOutcome::Interrupted { .. } => Some("interrupted"),
+        }
+    }
+}
+
+impl std::fmt::Display for SolveOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            SolveOutcome::Solved(solution) => write!(f, "solved with nonce {}", solution.nonce),
+            SolveOutcome::Exhausted => write!(f, "every nonce was searched"),
+            SolveOutcome::MaxAttempts => write!(f, "the attempt limit was reached"),
+            SolveOutcome::Watchdog => write!(f, "gave up as too slow"),
+            SolveOutcome::Timeout => write!(f, "the deadline passed"),
+            SolveOutcome::Interrupted { .. } => write!(f, "interrupted"),
+        }
+    }
+}
+
 #[derive(Clone)]
 struct HashWorker {
+    id: usize,
     start_nonce: Nonce,
-    end_nonce: Nonce, // not inclusive
+    end_nonce: Nonce,          // not inclusive
+    stride: u64,               // the worker searches every `stride`th nonce from `start_nonce`
+    covered: Arc<NonceRanges>, // nonces searched in a previous run, which are skipped
     hasher: Sha256Hasher,
     out_handle: Sender<HashResponse>,
-    target: Sha256Hash,
+    predicate: HashPredicate,
+    stop: Arc<AtomicBool>, // set by the farm once it has its answer
+    batch_size: u64,       // misses reported to the farm in a single message
+}
+
+// how many nonces a worker hashes between checks of the farm's stop flag
+const STOP_CHECK_INTERVAL: u64 = 4096;
+
+// the core each of `num_workers` workers is pinned to, with worker i on
+// core i % `num_cores`
+#[cfg(feature = "affinity")]
+fn core_assignments(num_workers: usize, num_cores: usize) -> Vec<usize> {
+    match num_cores {
+        0 => vec![],
+        _ => (0..num_workers).map(|i| i % num_cores).collect(),
+    }
+}
+
+// how many misses a worker reports at once; sending each on its own makes
+// the channel, not hashing, the bottleneck
+pub const DEFAULT_BATCH_SIZE: u64 = 4096;
+
+fn gcd(a: u64, b: u64) -> u64 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
 }
 
 impl HashWorker {
+    // searches the worker's range, reporting every solution it finds; stops
+    // once the farm says so or is no longer listening
+    // (is_multiple_of and is_none_or are too new for the rustc we support)
+    #[allow(
+        unknown_lints,
+        clippy::manual_is_multiple_of,
+        clippy::unnecessary_map_or
+    )]
     fn solve(&self) -> () {
-        let mut n = self.start_nonce;
-        while n < self.end_nonce {
+        let mut best: Option<Sha256Hash> = None; // the lowest hash this worker has seen
+        let mut next = self.next_nonce(self.start_nonce);
+        let mut hashed: u64 = 0; // by this worker, including any solution
+        let mut unreported: u64 = 0; // misses not yet sent to the farm
+        let mut last_miss: Nonce = 0;
+        while let Some(n) = next {
+            hashed += 1;
+            if hashed % STOP_CHECK_INTERVAL == 0 && self.stop.load(Ordering::Relaxed) {
+                return;
+            }
             let hash_result = self.hasher.hash_with_nonce(n);
-            if hash_result < self.target {
-                self.out_handle
-                    .send(HashResponse::Success(HashSolution {
-                        attempts: 0,
+            let response = match (self.predicate)(&hash_result) {
+                true => Some(HashResponse::Success(
+                    self.id,
+                    HashSolution {
+                        attempts: hashed,
                         hash: hash_result,
                         nonce: n,
-                    }))
-                    .unwrap_or_else(|_| return);
-                return;
-            } else {
-                self.out_handle
-                    .send(HashResponse::Miss)
-                    .unwrap_or_else(|_| return);
+                        nonce_high: self.hasher.nonce_high,
+                    },
+                )),
+                false => {
+                    if best.as_ref().map_or(true, |b| hash_result < *b) {
+                        best = Some(hash_result.clone());
+                        if self
+                            .out_handle
+                            .send(HashResponse::Best(hash_result))
+                            .is_err()
+                        {
+                            return;
+                        }
+                    }
+                    unreported += 1;
+                    last_miss = n;
+                    match unreported >= self.batch_size {
+                        true => {
+                            unreported = 0;
+                            Some(HashResponse::Miss(self.id, self.batch_size, n))
+                        }
+                        false => None,
+                    }
+                }
+            };
+            if let Some(response) = response {
+                if self.out_handle.send(response).is_err() {
+                    return;
+                }
             }
-            n += 1;
+            next = n.checked_add(self.stride).and_then(|n| self.next_nonce(n));
+        }
+        // the rest of the last batch
+        if unreported > 0
+            && self
+                .out_handle
+                .send(HashResponse::Miss(self.id, unreported, last_miss))
+                .is_err()
+        {
+            return;
         }
         self.out_handle
-            .send(HashResponse::NoSolution)
+            .send(HashResponse::NoSolution(self.id, hashed))
             .unwrap_or_else(|_| return);
     }
 }
 
+impl HashWorker {
+    // the first nonce from `from` on that's this worker's to search and
+    // wasn't covered by a previous run
+    fn next_nonce(&self, from: Nonce) -> Option<Nonce> {
+        let residue = self.start_nonce % self.stride;
+        let mut n = from;
+        loop {
+            let uncovered = self.covered.next_uncovered(n);
+            let offset = (residue + self.stride - uncovered % self.stride) % self.stride;
+            n = uncovered.checked_add(offset)?;
+            if n >= self.end_nonce {
+                return None;
+            }
+            if !self.covered.contains(n) {
+                return Some(n);
+            }
+        }
+    }
+}
+
 enum HashResponse {
-    Success(HashSolution),
-    Miss,                // worker attempted a hash but it wasn't successful
-    NoSolution,          // worker went through assigned nonce range with no solution
+    Success(usize, HashSolution), // by worker id, with the attempts that worker made
+    // worker (by id) attempted a batch of this many hashes without success,
+    // the last of them of the given nonce
+    Miss(usize, u64, Nonce),
+    // worker (by id) went through its assigned nonce range with no solution,
+    // after this many attempts in all
+    NoSolution(usize, u64),
+    Best(Sha256Hash),    // a worker's lowest hash so far, sent whenever it improves
     ProgressMessageTick, // sent at a consistent interval to print a progress message
 }
 
-pub struct HashWorkerFarm {
-    reply_handle: Receiver<HashResponse>,
-    response_sender: Sender<HashResponse>,
-    target: Sha256Hash,
-    workers: Vec<HashWorker>,
+// where solve progress is drawn; results always go to stdout, so progress on
+// stderr keeps them clean for pipelines
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressStream {
+    Stdout,
+    Stderr,
+    Hidden, // draws nothing, for --quiet or library callers that only want the result
 }
 
-impl HashWorkerFarm {
-    pub fn new(base: Vec<u8>, target: Sha256Hash, num_workers: u8) -> HashWorkerFarm {
-        let (response_sender, response_receiver) = channel();
-        let mut workers = Vec::new();
-        let mut nonce_marker: u64 = 0;
-        let range_per_nonce = std::u64::MAX / num_workers as u64;
-        for i in 0..num_workers {
-            let base_clone = base.clone();
-            workers.push(HashWorker {
-                start_nonce: nonce_marker,
-                end_nonce: match i + 1 == num_workers {
-                    false => nonce_marker + range_per_nonce as u64,
-                    true => std::u64::MAX,
-                },
-                target: target.clone(),
-                hasher: Sha256Hasher::new(base_clone),
-                out_handle: response_sender.clone(),
-            });
-            nonce_marker = nonce_marker + range_per_nonce;
+impl ProgressStream {
+    fn draw_target(self) -> ProgressDrawTarget {
+        match self {
+            ProgressStream::Stdout => ProgressDrawTarget::stdout(),
+            ProgressStream::Stderr => ProgressDrawTarget::stderr(),
+            ProgressStream::Hidden => ProgressDrawTarget::hidden(),
         }
-        HashWorkerFarm {
-            reply_handle: response_receiver,
-            response_sender: response_sender,
-            target: target,
-            workers: workers,
+    }
+
+    fn is_terminal(self) -> bool {
+        match self {
+            ProgressStream::Stdout => std::io::stdout().is_terminal(),
+            ProgressStream::Stderr => std::io::stderr().is_terminal(),
+            ProgressStream::Hidden => false,
         }
     }
 
-    pub fn solve(self: Box<Self>) -> Option<HashSolution> {
-        let mut attempt_count: u64 = 0;
-        let mut completed_workers: u8 = 0;
+    fn write_line(self, line: &str) -> () {
+        // progress is best effort; a closed stream shouldn't stop the solve
+        let _ = match self {
+            ProgressStream::Stdout => writeln!(std::io::stdout(), "{}", line),
+            ProgressStream::Stderr => writeln!(std::io::stderr(), "{}", line),
+            ProgressStream::Hidden => Ok(()),
+        };
+    }
+}
 
-        let expected_attempts = self.target.expected_attempts_to_solve();
-        let p90_attempts = self.target.p90_attempts_to_solve();
-        let p99_attempts = self.target.p99_attempts_to_solve();
-        let all_attempts = std::u64::MAX - 1; // duration to finish time doesn't work without the -1
+// whether styled output, like the progress bars, is colored
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorChoice {
+    Auto, // only when writing to a terminal
+    Always,
+    Never,
+}
 
-        // progress bar
-        let progress_bar_style = ProgressStyle::default_bar()
-            .template("{spinner:.green} {prefix} [{bar:40.green}] {percent}% ({eta})")
-            .progress_chars("█▉▊▋▌▍▎▏  ");
+impl ColorChoice {
+    // indicatif styles through the console crate, so this covers the progress bars
+    pub fn apply(self) -> () {
+        match self {
+            ColorChoice::Auto => {}
+            ColorChoice::Always => console::set_colors_enabled(true),
+            ColorChoice::Never => console::set_colors_enabled(false),
+        }
+    }
+}
 
-        let m = MultiProgress::new();
-        let expected_progress_bar = m.add(ProgressBar::new(expected_attempts));
-        let p90_progress_bar = m.add(ProgressBar::new(p90_attempts));
-        let p99_progress_bar = m.add(ProgressBar::new(p99_attempts));
+impl FromStr for ColorChoice {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(ColorChoice::Auto),
+            "always" => Ok(ColorChoice::Always),
+            "never" => Ok(ColorChoice::Never),
+            _ => Err(format!("Unknown color choice {}", s)),
+        }
+    }
+}
+
+// a progress bar template with a bar `bar_width` characters wide; without
+// color there's no color spec at all, so nothing can slip escape codes in
+fn progress_template(bar_width: usize, color: bool) -> String {
+    match color {
+        true => format!(
+            "{{spinner:.green}} {{prefix}} [{{bar:{}.green}}] {{percent}}% ({{eta}})",
+            bar_width
+        ),
+        false => format!(
+            "{{spinner}} {{prefix}} [{{bar:{}}}] {{percent}}% ({{eta}})",
+            bar_width
+        ),
+    }
+}
+
+// progress isn't reported until this long into a solve, when the hashrate
+// is more than noise
+const MIN_HASHRATE_ELAPSED: Duration = Duration::from_millis(100);
+
+// in H/s; 0 when no time has passed
+pub fn compute_hashrate(attempts: u64, elapsed: Duration) -> f64 {
+    match elapsed.as_secs_f64() {
+        secs if secs > 0.0 => attempts as f64 / secs,
+        _ => 0.0,
+    }
+}
+
+// the hashrate is measured for this long before deciding whether to give up
+const GIVE_UP_MEASUREMENT: Duration = Duration::from_secs(1);
+
+// why a solve should give up, if it's projected to take longer than `limit`
+fn give_up_reason(
+    expected_attempts: u64,
+    attempts: u64,
+    elapsed: Duration,
+    limit: Duration,
+) -> Option<String> {
+    let hash_rate = compute_hashrate(attempts, elapsed);
+    let remaining = expected_attempts.saturating_sub(attempts) as f64 / hash_rate;
+    let projected = elapsed.as_secs_f64() + remaining;
+    match projected > limit.as_secs_f64() {
+        true => Some(format!(
+            "Giving up: the solve is projected to take {} at {:.1}kh/s, longer than {}",
+            HumanDuration(Duration::try_from_secs_f64(projected).unwrap_or(Duration::MAX)),
+            hash_rate / 1000.0,
+            HumanDuration(limit)
+        )),
+        false => None,
+    }
+}
+
+// progress bars aren't drawn when the stream isn't a terminal, so a plain
+// progress line is written this often instead
+const PLAIN_PROGRESS_INTERVAL: Duration = Duration::from_secs(5);
+
+// how the nonces are divided between workers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PartitionScheme {
+    Contiguous,  // each worker searches its own block of nonces
+    Interleaved, // worker i of n searches i, i + n, i + 2n, ...
+}
+
+// hashing needs very little stack, so workers don't need the platform default
+pub const DEFAULT_WORKER_STACK_SIZE: usize = 256 * 1024;
+
+// decides whether a hash solves the challenge
+type HashPredicate = Arc<dyn Fn(&Sha256Hash) -> bool + Send + Sync>;
+
+// receives the final solve state when a farm stops
+type ExitStateHandler = Box<dyn Fn(&SolveState) + Send>;
+
+pub struct HashWorkerFarm {
+    reply_handle: Receiver<HashResponse>,
+    response_sender: Sender<HashResponse>,
+    target: Sha256Hash,
+    workers: Vec<HashWorker>,
+    worker_stack_size: usize, // bytes
+    #[cfg_attr(not(feature = "affinity"), allow(dead_code))]
+    pin_cores: bool, // each worker thread is kept on one core, see set_pin_cores
+    max_attempts: Option<u64>, // give up after this many attempts across all workers
+    max_attempts_per_worker: Option<u64>, // give up once every worker has made this many
+    covered: NonceRanges,     // nonces searched before this farm was created
+    checkpoint_path: Option<PathBuf>,
+    save_on_exit: Option<(PathBuf, SolveState)>,
+    on_exit: Option<(ExitStateHandler, SolveState)>,
+    progress_stream: ProgressStream,
+    show_best: bool,
+    partition: PartitionScheme,
+    warmup: Duration, // hashing discarded before the hashrate test measures
+    give_up_if_slower_than: Option<Duration>,
+    deadline: Option<Duration>, // how long the solve may run for
+    interrupt: Option<Arc<AtomicBool>>,
+    stop: Arc<AtomicBool>, // shared with the workers, which return once it's set
+}
+
+impl HashWorkerFarm {
+    pub fn new(base: Vec<u8>, target: Sha256Hash, num_workers: u8) -> HashWorkerFarm {
+        HashWorkerFarm::new_with_goal(base, SolveGoal::Target(target), num_workers)
+    }
+
+    pub fn new_with_goal(base: Vec<u8>, goal: SolveGoal, num_workers: u8) -> HashWorkerFarm {
+        HashWorkerFarm::resume(base, goal, num_workers, NonceRanges::new())
+    }
+
+    // like `new`, but the workers divide [start, u64::MAX) between them, ex:
+    // to carry on from the progress an interrupted solve last reported
+    pub fn new_starting_at(
+        base: Vec<u8>,
+        target: Sha256Hash,
+        num_workers: u8,
+        start: Nonce,
+    ) -> HashWorkerFarm {
+        HashWorkerFarm::resume(
+            base,
+            SolveGoal::Target(target),
+            num_workers,
+            NonceRanges::outside(start, std::u64::MAX),
+        )
+    }
+
+    // builds a farm that skips the nonces already covered by a previous
+    // solve, splitting the rest evenly between however many workers we have now
+    pub fn resume(
+        base: Vec<u8>,
+        goal: SolveGoal,
+        num_workers: u8,
+        covered: NonceRanges,
+    ) -> HashWorkerFarm {
+        let target = goal.equivalent_target();
+        let predicate = Arc::new(move |hash: &Sha256Hash| goal.is_met_by(hash));
+        HashWorkerFarm::with_predicate(base, predicate, target, num_workers, covered)
+    }
+
+    // builds a farm whose solutions are the hashes `predicate` accepts rather
+    // than those meeting a target. There's no target to estimate from, so
+    // progress is shown as if any hash might solve it
+    pub fn new_with_predicate<P>(base: Vec<u8>, predicate: P, num_workers: u8) -> HashWorkerFarm
+    where
+        P: Fn(&Sha256Hash) -> bool + Send + Sync + 'static,
+    {
+        HashWorkerFarm::with_predicate(
+            base,
+            Arc::new(predicate),
+            Sha256Hash::from_str(
+                &"ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+            )
+            .unwrap(),
+            num_workers,
+            NonceRanges::new(),
+        )
+    }
+
+    // searches for a nonce whose hash `predicate` accepts
+    pub fn solve_predicate<P>(base: Vec<u8>, predicate: P, num_workers: u8) -> Option<HashSolution>
+    where
+        P: Fn(&Sha256Hash) -> bool + Send + Sync + 'static,
+    {
+        let farm = HashWorkerFarm::new_with_predicate(base, predicate, num_workers);
+        HashWorkerFarm::solve(Box::from(farm))
+    }
+
+    // `target` is only used for progress estimates
+    fn with_predicate(
+        base: Vec<u8>,
+        predicate: HashPredicate,
+        target: Sha256Hash,
+        num_workers: u8,
+        covered: NonceRanges,
+    ) -> HashWorkerFarm {
+        let (response_sender, response_receiver) = channel();
+        let shared_covered = Arc::new(covered.clone());
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut workers = Vec::new();
+        // the first worker starts at the first nonce left to search
+        let first = covered.next_uncovered(0);
+        let spans = covered.split_uncovered(first, std::u64::MAX, num_workers);
+        for (i, &(start_nonce, end_nonce)) in spans.iter().enumerate() {
+            let base_clone = base.clone();
+            workers.push(HashWorker {
+                id: i,
+                start_nonce: start_nonce,
+                end_nonce: end_nonce,
+                stride: 1,
+                covered: shared_covered.clone(),
+                predicate: predicate.clone(),
+                stop: stop.clone(),
+                batch_size: DEFAULT_BATCH_SIZE,
+                hasher: Sha256Hasher::new(base_clone),
+                out_handle: response_sender.clone(),
+            });
+        }
+        HashWorkerFarm {
+            reply_handle: response_receiver,
+            response_sender: response_sender,
+            target: target,
+            workers: workers,
+            worker_stack_size: DEFAULT_WORKER_STACK_SIZE,
+            pin_cores: false,
+            max_attempts: None,
+            max_attempts_per_worker: None,
+            covered: covered,
+            checkpoint_path: None,
+            save_on_exit: None,
+            on_exit: None,
+            progress_stream: ProgressStream::Stderr,
+            show_best: false,
+            partition: PartitionScheme::Contiguous,
+            warmup: Duration::from_secs(0),
+            give_up_if_slower_than: None,
+            deadline: None,
+            interrupt: None,
+            stop: stop,
+        }
+    }
+
+    pub fn set_partition(&mut self, partition: PartitionScheme) -> () {
+        let num_workers = self.workers.len();
+        let first = self.covered.next_uncovered(0);
+        let spans = self
+            .covered
+            .split_uncovered(first, std::u64::MAX, num_workers as u8);
+        for (i, worker) in self.workers.iter_mut().enumerate() {
+            match partition {
+                PartitionScheme::Contiguous => {
+                    worker.start_nonce = spans[i].0;
+                    worker.end_nonce = spans[i].1;
+                    worker.stride = 1;
+                }
+                PartitionScheme::Interleaved => {
+                    worker.start_nonce = first.saturating_add(i as Nonce);
+                    worker.end_nonce = std::u64::MAX;
+                    worker.stride = num_workers as u64;
+                }
+            }
+        }
+        self.partition = partition;
+    }
+
+    // with 128-bit nonces, worker i searches every nonce whose upper 64 bits
+    // are i rather than a share of the 64-bit range. Covered ranges, and so
+    // checkpoints and resuming, only describe the lower 64 bits
+    pub fn set_nonce_width(&mut self, width: NonceWidth) -> () {
+        match width {
+            NonceWidth::Bits64 => self.set_partition(self.partition),
+            NonceWidth::Bits128 => {
+                for worker in self.workers.iter_mut() {
+                    worker.start_nonce = 0;
+                    worker.end_nonce = std::u64::MAX;
+                    worker.stride = 1;
+                }
+            }
+        }
+        for worker in self.workers.iter_mut() {
+            worker.hasher.nonce_width = width;
+            worker.hasher.nonce_high = match width {
+                NonceWidth::Bits64 => 0,
+                NonceWidth::Bits128 => worker.id as u64,
+            };
+        }
+    }
+
+    // the byte order nonces are hashed in, which must match the lock's
+    pub fn set_nonce_byte_order(&mut self, order: NonceByteOrder) -> () {
+        for worker in self.workers.iter_mut() {
+            worker.hasher.nonce_byte_order = order;
+        }
+    }
+
+    // how many misses each worker reports at once; 1 reports every attempt
+    pub fn set_batch_size(&mut self, batch_size: u64) -> () {
+        for worker in self.workers.iter_mut() {
+            worker.batch_size = batch_size.max(1);
+        }
+    }
+
+    pub fn set_algorithm(&mut self, algorithm: HashAlgorithm) -> () {
+        for worker in self.workers.iter_mut() {
+            worker.hasher.algorithm = algorithm;
+        }
+    }
+
+    pub fn set_progress_stream(&mut self, stream: ProgressStream) -> () {
+        self.progress_stream = stream;
+    }
+
+    // adds the lowest hash seen so far to each progress message
+    pub fn set_show_best(&mut self, show_best: bool) -> () {
+        self.show_best = show_best;
+    }
+
+    // once the hashrate has been measured, gives up on the solve if it's
+    // projected to take longer than `limit` on average
+    pub fn set_give_up_if_slower_than(&mut self, limit: Option<Duration>) -> () {
+        self.give_up_if_slower_than = limit;
+    }
+
+    // stops the solve once it's been running for `deadline`, whether or not
+    // there's a solution
+    pub fn set_deadline(&mut self, deadline: Option<Duration>) -> () {
+        self.deadline = deadline;
+    }
+
+    // stops the solve with SolveOutcome::Interrupted, or the hashrate test
+    // with the rate so far, at the next progress tick after `interrupt` is
+    // set, ex: by a Ctrl-C handler
+    pub fn set_interrupt(&mut self, interrupt: Arc<AtomicBool>) -> () {
+        self.interrupt = Some(interrupt);
+    }
+
+    pub fn set_warmup(&mut self, warmup: Duration) -> () {
+        self.warmup = warmup;
+    }
+
+    pub fn set_worker_stack_size(&mut self, bytes: usize) -> () {
+        self.worker_stack_size = bytes;
+    }
+
+    // pins worker i to core i % the number of cores, so the OS doesn't move
+    // workers between cores mid-solve. Only has an effect when built with
+    // the "affinity" feature
+    pub fn set_pin_cores(&mut self, pin_cores: bool) -> () {
+        self.pin_cores = pin_cores;
+    }
+
+    pub fn set_max_attempts(&mut self, max_attempts: Option<u64>) -> () {
+        self.max_attempts = max_attempts;
+    }
+
+    // on every progress tick, the nonces searched so far (including those
+    // from a resumed solve) are written to this file. The first nonce the
+    // file doesn't cover is the lowest any worker has yet to reach, so it's
+    // always a safe --start after a crash
+    pub fn set_checkpoint_path(&mut self, path: Option<PathBuf>) -> () {
+        self.checkpoint_path = path;
+    }
+
+    // when the solve finishes, gives up or runs out of nonces, `state` plus
+    // this session's progress is written to this file
+    pub fn set_save_on_exit(&mut self, path: PathBuf, state: SolveState) -> () {
+        self.save_on_exit = Some((path, state));
+    }
+
+    // the nonces searched so far, given the next nonce each worker will search
+    fn searched(&self, positions: &[Nonce]) -> NonceRanges {
+        let mut covered = self.covered.clone();
+        match self.partition {
+            PartitionScheme::Contiguous => {
+                for (worker, &position) in self.workers.iter().zip(positions.iter()) {
+                    covered.insert(worker.start_nonce, position);
+                }
+            }
+            // interleaved workers have each searched every one of their nonces
+            // below their position, so everything below the lowest is searched
+            PartitionScheme::Interleaved => {
+                covered.insert(0, positions.iter().cloned().min().unwrap_or(0))
+            }
+        }
+        covered
+    }
+
+    // like `set_save_on_exit`, but the final state is handed to `handler`
+    // rather than written to a file
+    pub fn set_on_exit<F>(&mut self, state: SolveState, handler: F) -> ()
+    where
+        F: Fn(&SolveState) + Send + 'static,
+    {
+        self.on_exit = Some((Box::new(handler), state));
+    }
+
+    // `state` with this farm's progress added
+    fn updated_state(
+        &self,
+        state: &SolveState,
+        positions: &[Nonce],
+        attempts: u64,
+        elapsed: Duration,
+    ) -> SolveState {
+        let mut state = state.clone();
+        for &(start, end) in self.searched(positions).ranges() {
+            state.covered.insert(start, end);
+        }
+        state.attempts += attempts;
+        state.elapsed_ms += elapsed.as_millis() as u64;
+        state
+    }
+
+    fn save_state(&self, positions: &[Nonce], attempts: u64, elapsed: Duration) -> () {
+        if let Some((path, state)) = &self.save_on_exit {
+            let state = self.updated_state(state, positions, attempts, elapsed);
+            if let Err(e) = state.save(path) {
+                warn!("Unable to save solve state {}: {}", path.display(), e);
+            }
+        }
+        if let Some((handler, state)) = &self.on_exit {
+            handler(&self.updated_state(state, positions, attempts, elapsed));
+        }
+    }
+
+    fn spawn_workers(&self) -> () {
+        #[cfg(feature = "affinity")]
+        let core_ids = match self.pin_cores {
+            true => core_affinity::get_core_ids().unwrap_or_default(),
+            false => vec![],
+        };
+        #[cfg(feature = "affinity")]
+        let assignments = core_assignments(self.workers.len(), core_ids.len());
+        for i in 0..self.workers.len() {
+            let mut worker = self.workers[i].clone();
+            // batches that divide the attempt limits add up to them exactly
+            for limit in self
+                .max_attempts
+                .iter()
+                .chain(&self.max_attempts_per_worker)
+            {
+                worker.batch_size = gcd(worker.batch_size, *limit);
+            }
+            #[cfg(feature = "affinity")]
+            let core_id = assignments.get(i).map(|&core| core_ids[core]);
+            std::thread::Builder::new()
+                .stack_size(self.worker_stack_size)
+                .spawn(move || {
+                    #[cfg(feature = "affinity")]
+                    if let Some(core_id) = core_id {
+                        // pinning is best effort; the worker runs either way
+                        core_affinity::set_for_current(core_id);
+                    }
+                    worker.solve();
+                })
+                .expect("Unable to spawn worker thread");
+        }
+    }
+
+    pub fn solve(self: Box<Self>) -> Option<HashSolution> {
+        self.solve_outcome().solution()
+    }
+
+    // like `solve`, but gives up with no solution once `deadline` has passed
+    pub fn solve_with_deadline(mut self: Box<Self>, deadline: Duration) -> Option<HashSolution> {
+        self.set_deadline(Some(deadline));
+        self.solve()
+    }
+
+    // like `solve`, but says why there's no solution
+    pub fn solve_outcome(self: Box<Self>) -> SolveOutcome {
+        self.solve_verified_outcome(|_| true)
+    }
+
+    // like `solve`, but a candidate solution is only returned if `verify`
+    // accepts it; rejected candidates are skipped and the search continues
+    pub fn solve_verified<F>(self: Box<Self>, verify: F) -> Option<HashSolution>
+    where
+        F: FnMut(&HashSolution) -> bool + Send + 'static,
+    {
+        self.solve_verified_outcome(verify).solution()
+    }
+
+    pub fn solve_verified_outcome<F>(self: Box<Self>, verify: F) -> SolveOutcome
+    where
+        F: FnMut(&HashSolution) -> bool + Send + 'static,
+    {
+        let expected_attempts = self.target.expected_attempts_to_solve();
+        let p90_attempts = self.target.p90_attempts_to_solve();
+        let p99_attempts = self.target.p99_attempts_to_solve();
+        let all_attempts = std::u64::MAX - 1; // duration to finish time doesn't work without the -1
+
+        // progress bar
+        let template = progress_template(40, console::colors_enabled());
+        let progress_bar_style = ProgressStyle::default_bar()
+            .template(&template)
+            .progress_chars("█▉▊▋▌▍▎▏  ");
+
+        let m = MultiProgress::new();
+        m.set_draw_target(self.progress_stream.draw_target());
+        let expected_progress_bar = m.add(ProgressBar::new(expected_attempts));
+        let p90_progress_bar = m.add(ProgressBar::new(p90_attempts));
+        let p99_progress_bar = m.add(ProgressBar::new(p99_attempts));
         let all_progress_bar = m.add(ProgressBar::new(all_attempts));
 
         let progress_bars = vec![
@@ -273,137 +1369,255 @@ impl HashWorkerFarm {
             }
         }
         progress_bars[3].set_style(
-            progress_bar_style.clone().template(
-                "{spinner:.green} {prefix} [{bar:40.green}] {percent}% ({eta})\n{wide_msg}",
-            ),
+            progress_bar_style
+                .clone()
+                .template(&format!("{}\n{{wide_msg}}", template)),
         );
 
-        // run workers
-        for i in 0..self.workers.len() {
-            let worker = self.workers[i].clone();
-            std::thread::spawn(move || {
-                worker.solve();
+        let progress_stream = self.progress_stream;
+        let show_best = self.show_best;
+
+        // the progress bars are drawn until the solve ends on its own thread
+        let computation_result = std::thread::spawn(move || {
+            let plain_progress = !progress_stream.is_terminal();
+            let mut last_plain_progress: Option<Instant> = None;
+            let outcome = self.solve_verified_with_callback(verify, |progress| {
+                let attempt_count = progress.attempts;
+                let expected_completion = match estimated_completion_time(
+                    expected_attempts.saturating_sub(attempt_count),
+                    progress.hash_rate,
+                    SystemTime::now(),
+                ) {
+                    Some(time) => humantime::format_rfc3339_seconds(time).to_string(),
+                    None if attempt_count >= expected_attempts => {
+                        "past expected attempts".to_string()
+                    }
+                    None => "unknown".to_string(),
+                };
+                let mut message = format!(
+                    "Elapsed Time: {}, Hash Rate: {:.1}kh/s, Expected completion: {}",
+                    HumanDuration(progress.elapsed),
+                    progress.hash_rate / 1000.0,
+                    expected_completion
+                );
+                if let (true, Some(hash)) = (show_best, &progress.best) {
+                    message.push_str(&format!(
+                        ", Best so far: {} ({} leading zero bits)",
+                        hash,
+                        hash.leading_zero_bits()
+                    ));
+                }
+                progress_bars[3].set_message(&message);
+                let plain_progress_due = match last_plain_progress {
+                    Some(last) => last.elapsed() >= PLAIN_PROGRESS_INTERVAL,
+                    None => true,
+                };
+                if plain_progress && plain_progress_due {
+                    progress_stream.write_line(&message);
+                    last_plain_progress = Some(Instant::now());
+                }
+                for progress_bar in &progress_bars {
+                    progress_bar.set_position(attempt_count);
+                }
+                if attempt_count < expected_attempts {
+                    // do we need to do something?
+                } else if attempt_count < p90_attempts {
+                    progress_bars[0].finish_and_clear();
+                } else if attempt_count < p99_attempts {
+                    progress_bars[1].finish_and_clear();
+                } else {
+                    progress_bars[2].finish_and_clear();
+                }
             });
-        }
+            // cleared so the terminal is left as it was
+            for progress_bar in &progress_bars {
+                progress_bar.finish_and_clear();
+            }
+            outcome
+        });
+        m.join_and_clear().unwrap();
+        computation_result.join().unwrap()
+    }
+
+    // solves without drawing progress bars, handing `callback` the progress
+    // on every tick instead, ex: to show it in a GUI
+    pub fn solve_with_callback<P>(self: Box<Self>, callback: P) -> SolveOutcome
+    where
+        P: FnMut(Progress),
+    {
+        self.solve_verified_with_callback(|_| true, callback)
+    }
+
+    #[allow(unknown_lints, clippy::unnecessary_map_or)] // is_none_or is too new
+    fn solve_verified_with_callback<F, P>(
+        self: Box<Self>,
+        mut verify: F,
+        mut callback: P,
+    ) -> SolveOutcome
+    where
+        F: FnMut(&HashSolution) -> bool,
+        P: FnMut(Progress),
+    {
+        let mut attempt_count: u64 = 0;
+        let mut completed_workers: u8 = 0;
+        let expected_attempts = self.target.expected_attempts_to_solve();
+
+        // run workers
+        self.spawn_workers();
 
-        // implement a timer thread to update the progress bars
+        // implement a timer thread to report progress
         // since that operation is relatively expensive, we don't want to
         // do it every time a worker records a miss
         let timer_sender_handle = self.response_sender.clone();
 
         std::thread::spawn(move || loop {
             std::thread::sleep(std::time::Duration::from_millis(333));
-            timer_sender_handle
+            // the solve is over once nobody is listening
+            if timer_sender_handle
                 .send(HashResponse::ProgressMessageTick)
-                .unwrap_or_else(|_| return);
+                .is_err()
+            {
+                return;
+            }
         });
 
         // handle worker responses
-        let computation_result = std::thread::spawn(move || {
-            let start_time = Instant::now();
-            for response in self.reply_handle.iter() {
-                match response {
-                    HashResponse::Success(solution) => {
-                        for progress_bar in &progress_bars {
-                            progress_bar.finish_and_clear();
-                        }
-                        return Some(HashSolution {
-                            nonce: solution.nonce,
-                            attempts: attempt_count,
-                            hash: solution.hash,
-                        });
-                    }
-                    HashResponse::Miss => {
+        let start_time = Instant::now();
+        let mut best: Option<Sha256Hash> = None;
+        // the next nonce each worker will search
+        let mut positions: Vec<Nonce> = self.workers.iter().map(|w| w.start_nonce).collect();
+        // the attempts counted so far from each worker
+        let mut worker_attempts = vec![0u64; self.workers.len()];
+        let outcome = loop {
+            let response = match self.reply_handle.recv() {
+                Ok(response) => response,
+                // every sender is gone, so no worker is left searching
+                Err(_) => break SolveOutcome::Exhausted,
+            };
+            match response {
+                HashResponse::Success(id, solution) => {
+                    if !verify(&solution) {
                         attempt_count += 1;
+                        worker_attempts[id] += 1;
+                        continue;
                     }
-                    HashResponse::NoSolution => {
-                        completed_workers += 1;
-                        if completed_workers == self.workers.len() as u8 {
-                            for progress_bar in &progress_bars {
-                                progress_bar.finish_and_clear();
-                            }
-                            return None;
+                    // the solving worker's own count replaces its misses
+                    // here, which may not all have been received yet
+                    attempt_count = attempt_count - worker_attempts[id] + solution.attempts;
+                    self.save_state(&positions, attempt_count, start_time.elapsed());
+                    break SolveOutcome::Solved(HashSolution {
+                        nonce: solution.nonce,
+                        nonce_high: solution.nonce_high,
+                        attempts: attempt_count,
+                        hash: solution.hash,
+                    });
+                }
+                HashResponse::Miss(id, count, nonce) => {
+                    attempt_count += count;
+                    worker_attempts[id] += count;
+                    positions[id] = nonce + 1;
+                    let limit_reached = self.max_attempts.is_some_and(|max| attempt_count >= max)
+                        || self
+                            .max_attempts_per_worker
+                            .is_some_and(|max| worker_attempts.iter().all(|&a| a >= max));
+                    if limit_reached {
+                        // dropping the receiver on return stops the workers
+                        self.save_state(&positions, attempt_count, start_time.elapsed());
+                        break SolveOutcome::MaxAttempts;
+                    }
+                }
+                HashResponse::Best(hash) => {
+                    if best.as_ref().map_or(true, |b| hash < *b) {
+                        best = Some(hash);
+                    }
+                }
+                HashResponse::NoSolution(id, attempts) => {
+                    // the worker's own count replaces the sum of its misses
+                    attempt_count = attempt_count - worker_attempts[id] + attempts;
+                    worker_attempts[id] = attempts;
+                    completed_workers += 1;
+                    if completed_workers == self.workers.len() as u8 {
+                        self.save_state(&positions, attempt_count, start_time.elapsed());
+                        break SolveOutcome::Exhausted;
+                    }
+                }
+                HashResponse::ProgressMessageTick => {
+                    if let Some(path) = &self.checkpoint_path {
+                        write_checkpoint(path, &self.searched(&positions));
+                    }
+                    if let Some(interrupt) = &self.interrupt {
+                        if interrupt.load(Ordering::SeqCst) {
+                            self.save_state(&positions, attempt_count, start_time.elapsed());
+                            break SolveOutcome::Interrupted {
+                                attempts: attempt_count,
+                                highest_nonce: positions.iter().cloned().max().unwrap_or(0),
+                            };
                         }
                     }
-                    HashResponse::ProgressMessageTick => {
-                        // print debug info
+                    if let Some(deadline) = self.deadline {
                         let elapsed = start_time.elapsed();
-                        let hash_rate = attempt_count as f64 / elapsed.as_secs() as f64;
-
-                        progress_bars[3].set_message(&format!(
-                            "Elapsed Time: {}, Hash Rate: {:.1}kh/s",
-                            HumanDuration(elapsed),
-                            hash_rate / 1000.0
-                        ));
-                        for progress_bar in &progress_bars {
-                            progress_bar.set_position(attempt_count);
+                        if elapsed >= deadline {
+                            self.save_state(&positions, attempt_count, elapsed);
+                            break SolveOutcome::Timeout;
                         }
-                        if attempt_count < expected_attempts {
-                            // do we need to do something?
-                        } else if attempt_count < p90_attempts {
-                            progress_bars[0].finish_and_clear();
-                        } else if attempt_count < p99_attempts {
-                            progress_bars[1].finish_and_clear();
-                        } else {
-                            progress_bars[2].finish_and_clear();
+                    }
+                    if let Some(limit) = self.give_up_if_slower_than {
+                        let elapsed = start_time.elapsed();
+                        let reason = match elapsed >= GIVE_UP_MEASUREMENT {
+                            true => {
+                                give_up_reason(expected_attempts, attempt_count, elapsed, limit)
+                            }
+                            false => None,
+                        };
+                        if let Some(reason) = reason {
+                            self.progress_stream.write_line(&reason);
+                            self.save_state(&positions, attempt_count, elapsed);
+                            break SolveOutcome::Watchdog;
                         }
                     }
+
+                    let elapsed = start_time.elapsed();
+                    if elapsed < MIN_HASHRATE_ELAPSED {
+                        continue;
+                    }
+                    callback(Progress {
+                        attempts: attempt_count,
+                        elapsed: elapsed,
+                        hash_rate: compute_hashrate(attempt_count, elapsed),
+                        best: best.clone(),
+                    });
                 }
             }
-            None
-        });
-        m.join_and_clear().unwrap();
-        computation_result.join().unwrap()
+        };
+        self.stop.store(true, Ordering::Relaxed);
+        outcome
     }
 
     // builds a farm used to test the hashrate of the machine
     pub fn new_test(num_workers: u8) -> HashWorkerFarm {
-        let (response_sender, response_receiver) = channel();
-        let base = b"anarbitrarystring".to_vec();
+        HashWorkerFarm::new_test_with_base(b"anarbitrarystring".to_vec(), num_workers)
+    }
+
+    // the hashrate depends on the base's length, so testing with a base as
+    // long as the real challenge's gives a more realistic figure
+    pub fn new_test_with_base(base: Vec<u8>, num_workers: u8) -> HashWorkerFarm {
         let target = Sha256Hash::from_str(
             &"0000000000000000000000000000000000000000000000000000000000000000".to_string(),
         )
         .unwrap(); // impossible to solve
-        let mut workers = Vec::new();
-        let mut nonce_marker: u64 = 0;
-        let range_per_nonce = std::u64::MAX / num_workers as u64;
-        for i in 0..num_workers {
-            let base_clone = base.clone();
-            workers.push(HashWorker {
-                start_nonce: nonce_marker,
-                end_nonce: match i + 1 == num_workers {
-                    false => nonce_marker + range_per_nonce as u64,
-                    true => std::u64::MAX,
-                },
-                target: target.clone(),
-                hasher: Sha256Hasher::new(base_clone),
-                out_handle: response_sender.clone(),
-            });
-            nonce_marker = nonce_marker + range_per_nonce;
-        }
-        HashWorkerFarm {
-            reply_handle: response_receiver,
-            response_sender: response_sender,
-            target: target,
-            workers: workers,
-        }
+        HashWorkerFarm::new(base, target, num_workers)
     }
 
     // runs the test worker farm and returns the hashrate in H/s
     pub fn run_test(&self, test_length_s: u64) -> u32 {
-        let mut attempt_count: u64 = 0;
-        let start_time = Instant::now();
+        let mut meter = RateMeter::new(Instant::now(), self.warmup);
 
-        for i in 0..self.workers.len() {
-            let worker = self.workers[i].clone();
-            std::thread::spawn(move || {
-                worker.solve();
-            });
-        }
+        self.spawn_workers();
 
         let pb = ProgressBar::new(test_length_s);
+        pb.set_draw_target(self.progress_stream.draw_target());
         let progress_bar_style = ProgressStyle::default_bar()
-            .template("{spinner:.green} {prefix} [{bar:32.green}] {percent}% ({eta})")
+            .template(&progress_template(32, console::colors_enabled()))
             .progress_chars("█▉▊▋▌▍▎▏  ");
         pb.set_style(progress_bar_style);
         let num_workers = self.workers.len();
@@ -419,77 +1633,592 @@ impl HashWorkerFarm {
         let timer_sender_handle = self.response_sender.clone();
         std::thread::spawn(move || loop {
             std::thread::sleep(std::time::Duration::from_millis(200));
-            timer_sender_handle
+            // the solve is over once nobody is listening
+            if timer_sender_handle
                 .send(HashResponse::ProgressMessageTick)
-                .unwrap_or_else(|_| return);
+                .is_err()
+            {
+                return;
+            }
         });
 
         for response in self.reply_handle.iter() {
             match response {
-                HashResponse::Success(_) => {
-                    // this is impossible with a properly formed test worker farm
-                    unreachable!("A worker found a solution in a test farm")
-                }
-                HashResponse::Miss => {
-                    attempt_count += 1;
-                }
-                HashResponse::NoSolution => {
-                    // this shouldn't happen in the time frame allowed;
-                    // we don't want workers to exaust their nonce range
-                    unreachable!("A worker completed work in a test farm")
+                // neither should happen with a properly formed test farm, but
+                // the rate so far still stands if they do
+                HashResponse::Success(..) | HashResponse::NoSolution(..) => break,
+                HashResponse::Miss(_, count, _) => {
+                    meter.attempts += count;
                 }
+                HashResponse::Best(_) => {}
                 HashResponse::ProgressMessageTick => {
-                    let elapsed = start_time.elapsed();
+                    if let Some(interrupt) = &self.interrupt {
+                        if interrupt.load(Ordering::SeqCst) {
+                            break;
+                        }
+                    }
+                    let now = Instant::now();
+                    meter.tick(now);
+                    let elapsed = match meter.measured(now) {
+                        Some(elapsed) => elapsed,
+                        None => continue, // still warming up
+                    };
                     pb.set_position(elapsed.as_secs());
                     if elapsed.as_secs() > test_length_s {
-                        pb.finish_and_clear();
-                        return (attempt_count as f64 / elapsed.as_secs() as f64) as u32;
+                        break;
                     }
                 }
             }
         }
-        unreachable!();
+        pb.finish_and_clear();
+        self.stop.store(true, Ordering::Relaxed);
+        meter.rate(Instant::now()) as u32
     }
 }
 
-pub fn nonce_to_bytes(nonce: Nonce) -> [u8; 8] {
-    let mut result = [0u8; 8];
-    result
-        .as_mut()
-        .write_u64::<LittleEndian>(nonce)
-        .expect("Unable to write");
-    result
+// counts hashrate test attempts, discarding those made while the CPU warms
+// up; frequency scaling makes the first seconds slower than steady state
+struct RateMeter {
+    started: Instant,
+    warmup_until: Instant,
+    measuring_since: Option<Instant>,
+    attempts: u64, // since measuring started, or during the warmup before then
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{Sha256Hash, Sha256Hasher};
-    use std::str::FromStr;
-    #[test]
-    fn it_creates_sha_hashes_from_hex() {
-        let hash = Sha256Hash::from_str(
-            &"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
-        )
-        .unwrap();
-        assert_eq!(
-            Sha256Hash {
-                value: [
-                    0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d,
-                    0xae, 0x22, 0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10,
-                    0xff, 0x61, 0xf2, 0x00, 0x15, 0xad,
-                ],
-            },
-            hash
-        );
+impl RateMeter {
+    fn new(start: Instant, warmup: Duration) -> RateMeter {
+        RateMeter {
+            started: start,
+            warmup_until: start + warmup,
+            measuring_since: None,
+            attempts: 0,
+        }
     }
 
-    #[test]
-    fn it_fails_to_create_hash_with_wrong_length() {
-        assert!(Sha256Hash::from_str(&"aa00bb".to_string()).is_err());
+    // starts measuring once the warmup is over
+    fn tick(&mut self, now: Instant) -> () {
+        if self.measuring_since.is_none() && now >= self.warmup_until {
+            self.measuring_since = Some(now);
+            self.attempts = 0;
+        }
     }
 
-    #[test]
-    fn it_hashes_abc() {
+    fn measured(&self, now: Instant) -> Option<Duration> {
+        self.measuring_since.map(|since| now - since)
+    }
+
+    // in H/s, over the warmup if the test stopped before measuring started
+    fn rate(&self, now: Instant) -> f64 {
+        let elapsed = self.measured(now).unwrap_or(now - self.started);
+        compute_hashrate(self.attempts, elapsed)
+    }
+}
+
+// solves several challenges at once on one pool of workers, returning the
+// first solution found along with the index of the challenge it solves
+pub struct MultiFarm {
+    challenges: Vec<(Vec<u8>, SolveGoal)>,
+    num_workers: u8, // shared between the challenges
+}
+
+impl MultiFarm {
+    pub fn new(challenges: Vec<(Vec<u8>, SolveGoal)>, num_workers: u8) -> MultiFarm {
+        MultiFarm {
+            challenges: challenges,
+            num_workers: num_workers,
+        }
+    }
+
+    // splits the workers as evenly as possible; every challenge gets at
+    // least one, even if that means more workers than asked for
+    fn worker_shares(&self) -> Vec<u8> {
+        let count = self.challenges.len();
+        (0..count)
+            .map(|i| {
+                let share = self.num_workers as usize / count
+                    + (i < self.num_workers as usize % count) as usize;
+                share.max(1) as u8
+            })
+            .collect()
+    }
+
+    pub fn solve(self) -> Option<(usize, HashSolution)> {
+        self.solve_with_forwarders().0
+    }
+
+    // also returns the threads forwarding each farm's responses, which all
+    // finish once the workers have stopped
+    fn solve_with_forwarders(self) -> (Option<(usize, HashSolution)>, Vec<JoinHandle<()>>) {
+        let (sender, receiver) = channel();
+        let shares = self.worker_shares();
+        let total_workers: usize = shares.iter().map(|&share| share as usize).sum();
+        let mut stops = vec![];
+        let mut forwarders = vec![];
+        for (i, ((base, goal), &share)) in self.challenges.into_iter().zip(&shares).enumerate() {
+            let farm = HashWorkerFarm::new_with_goal(base, goal, share);
+            farm.spawn_workers();
+            stops.push(farm.stop.clone());
+            // the rest of the farm, with its own sender, is dropped here so
+            // the replies end when the workers do
+            let replies = farm.reply_handle;
+            // tags each response with its challenge; stops once we stop listening
+            let sender = sender.clone();
+            forwarders.push(std::thread::spawn(move || {
+                for response in replies.iter() {
+                    if sender.send((i, response)).is_err() {
+                        return;
+                    }
+                }
+            }));
+        }
+
+        let mut attempts = vec![0u64; shares.len()];
+        let mut worker_attempts: Vec<Vec<u64>> = shares
+            .iter()
+            .map(|&share| vec![0; share as usize])
+            .collect();
+        let mut completed_workers = 0;
+        for (i, response) in receiver.iter() {
+            match response {
+                HashResponse::Success(id, solution) => {
+                    for stop in &stops {
+                        stop.store(true, Ordering::Relaxed);
+                    }
+                    let solution = HashSolution {
+                        attempts: attempts[i] - worker_attempts[i][id] + solution.attempts,
+                        ..solution
+                    };
+                    return (Some((i, solution)), forwarders);
+                }
+                HashResponse::Miss(id, count, _) => {
+                    attempts[i] += count;
+                    worker_attempts[i][id] += count;
+                }
+                HashResponse::NoSolution(id, count) => {
+                    attempts[i] = attempts[i] - worker_attempts[i][id] + count;
+                    worker_attempts[i][id] = count;
+                    completed_workers += 1;
+                    if completed_workers == total_workers {
+                        return (None, forwarders);
+                    }
+                }
+                HashResponse::Best(_) | HashResponse::ProgressMessageTick => {}
+            }
+        }
+        (None, forwarders)
+    }
+}
+
+// writes to a temporary file first so a crash mid-write can't leave a
+// truncated checkpoint behind
+fn write_checkpoint(path: &Path, covered: &NonceRanges) -> () {
+    let temp_path = path.with_extension("tmp");
+    let result = std::fs::write(&temp_path, covered.to_string())
+        .and_then(|_| std::fs::rename(&temp_path, path));
+    if let Err(e) = result {
+        warn!("Unable to write checkpoint {}: {}", path.display(), e);
+    }
+}
+
+// measures this machine's hashrate in H/s over `length` seconds, without
+// printing anything
+pub fn estimate_hashrate(num_workers: u8, length: u64) -> u32 {
+    estimate_hashrate_with(HashWorkerFarm::new_test(num_workers), length)
+}
+
+// like `estimate_hashrate`, with a test farm set up by the caller, ex: with a
+// realistic base or a warmup
+pub fn estimate_hashrate_with(mut test_farm: HashWorkerFarm, length: u64) -> u32 {
+    test_farm.set_progress_stream(ProgressStream::Hidden);
+    test_farm.run_test(length)
+}
+
+pub fn nonce_to_bytes(nonce: Nonce, order: NonceByteOrder) -> [u8; 8] {
+    let mut result = [0u8; 8];
+    match order {
+        NonceByteOrder::Little => result.as_mut().write_u64::<LittleEndian>(nonce),
+        NonceByteOrder::Big => result.as_mut().write_u64::<BigEndian>(nonce),
+    }
+    .expect("Unable to write");
+    result
+}
+
+// in 8 or 16 bytes
+pub fn nonce_to_bytes_with_width(
+    nonce: WideNonce,
+    width: NonceWidth,
+    order: NonceByteOrder,
+) -> Vec<u8> {
+    match (width, order) {
+        (NonceWidth::Bits64, _) => nonce_to_bytes(nonce as Nonce, order).to_vec(),
+        (NonceWidth::Bits128, NonceByteOrder::Little) => nonce.to_le_bytes().to_vec(),
+        (NonceWidth::Bits128, NonceByteOrder::Big) => nonce.to_be_bytes().to_vec(),
+    }
+}
+
+// byte layouts a device might expect a nonce in; solving hashes the nonce as
+// le8 unless the byte order is big-endian, and `as_hex_bytes` prints the same
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NonceEncoding {
+    Le8,
+    Be8,
+    Le4,
+    Be4,
+}
+
+impl NonceEncoding {
+    pub fn encode(self, nonce: Nonce) -> Result<Vec<u8>, String> {
+        let mut result = Vec::new();
+        match self {
+            NonceEncoding::Le8 => {
+                result.extend_from_slice(&nonce_to_bytes(nonce, NonceByteOrder::Little))
+            }
+            NonceEncoding::Be8 => result
+                .write_u64::<BigEndian>(nonce)
+                .expect("Unable to write"),
+            NonceEncoding::Le4 | NonceEncoding::Be4 => {
+                if nonce > std::u32::MAX as u64 {
+                    return Err(format!("{} doesn't fit in 4 bytes", nonce));
+                }
+                match self {
+                    NonceEncoding::Le4 => result.write_u32::<LittleEndian>(nonce as u32),
+                    _ => result.write_u32::<BigEndian>(nonce as u32),
+                }
+                .expect("Unable to write")
+            }
+        }
+        Ok(result)
+    }
+}
+
+impl FromStr for NonceEncoding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "le8" => Ok(NonceEncoding::Le8),
+            "be8" => Ok(NonceEncoding::Be8),
+            "le4" => Ok(NonceEncoding::Le4),
+            "be4" => Ok(NonceEncoding::Be4),
+            _ => Err(format!("Unknown nonce encoding {}", s)),
+        }
+    }
+}
+
+// appends the run index to the base so repeated solves of the same target
+// each explore a distinct challenge of the same difficulty; note that the
+// salted base, not the original, is what must be submitted with the nonce
+pub fn salt_base(base: &[u8], run_index: u32) -> Vec<u8> {
+    let mut salted = base.to_vec();
+    salted.extend_from_slice(run_index.to_string().as_bytes());
+    salted
+}
+
+// 9999-12-31T23:59:59Z, the latest time RFC 3339 can write
+const LATEST_RFC3339_SECS: u64 = 253_402_300_799;
+
+// the wall clock time `remaining_attempts` more attempts will be finished at
+// the given hashrate; `None` if there's nothing left to estimate, no
+// meaningful hashrate yet, or it's too far off to write as a timestamp
+pub fn estimated_completion_time(
+    remaining_attempts: u64,
+    hash_rate: f64, // hashes/s
+    now: SystemTime,
+) -> Option<SystemTime> {
+    if remaining_attempts == 0 || !hash_rate.is_finite() || hash_rate <= 0.0 {
+        return None;
+    }
+    let remaining_secs = remaining_attempts as f64 / hash_rate;
+    if remaining_secs > std::u64::MAX as f64 {
+        return None;
+    }
+    now.checked_add(Duration::from_millis((remaining_secs * 1000.0) as u64))
+        .filter(|&time| time <= UNIX_EPOCH + Duration::from_secs(LATEST_RFC3339_SECS))
+}
+
+// times a solve that only accepts solutions below `cluster_below`, which is
+// when the partition matters: contiguous workers past the first never reach
+// those nonces. Gives up once every worker has searched that many; a limit
+// across all workers could be used up by one before another even starts
+pub fn benchmark_partition(
+    base: &[u8],
+    goal: &SolveGoal,
+    num_workers: u8,
+    partition: PartitionScheme,
+    cluster_below: Nonce,
+) -> (Option<HashSolution>, Duration) {
+    let mut farm = HashWorkerFarm::new_with_goal(base.to_vec(), goal.clone(), num_workers);
+    farm.set_partition(partition);
+    farm.max_attempts_per_worker = Some(cluster_below);
+    let start_time = Instant::now();
+    let result = HashWorkerFarm::solve_verified(Box::from(farm), move |solution| {
+        solution.nonce < cluster_below
+    });
+    (result, start_time.elapsed())
+}
+
+// hashes `count` nonces starting at `start` and returns the nonce that
+// produced the numerically lowest hash, regardless of any target; `None` if
+// `count` is 0. The range stops at the last nonce, so near it fewer than
+// `count` are hashed, and `attempts` says how many were
+pub fn lowest_hash(base: &[u8], start: Nonce, count: u64) -> Option<HashSolution> {
+    let last = start.saturating_add(count.checked_sub(1)?);
+    let hasher = Sha256Hasher::new(base.to_vec());
+    let mut lowest = HashSolution {
+        nonce: start,
+        nonce_high: 0,
+        attempts: 1,
+        hash: hasher.hash_with_nonce(start),
+    };
+    for n in (start..=last).skip(1) {
+        let hash = hasher.hash_with_nonce(n);
+        lowest.attempts += 1;
+        if hash < lowest.hash {
+            lowest.nonce = n;
+            lowest.hash = hash;
+        }
+    }
+    Some(lowest)
+}
+
+// how many of a random sample of nonces solved the challenge
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProbeEstimate {
+    pub sampled: u64,
+    pub solutions: u64,
+}
+
+impl ProbeEstimate {
+    // the estimated fraction of nonces that are solutions
+    pub fn density(&self) -> f64 {
+        match self.sampled {
+            0 => 0.0,
+            sampled => self.solutions as f64 / sampled as f64,
+        }
+    }
+
+    pub fn expected_solutions(&self, range_size: u64) -> f64 {
+        self.density() * range_size as f64
+    }
+}
+
+// hashes `samples` nonces picked at random from [start, end) to estimate
+// how many solutions the whole range holds, without searching it
+pub fn probe(
+    base: &[u8],
+    goal: &SolveGoal,
+    start: Nonce,
+    end: Nonce,
+    samples: u64,
+) -> ProbeEstimate {
+    assert!(start < end, "Expected a non-empty nonce range");
+    let hasher = Sha256Hasher::new(base.to_vec());
+    let mut rng = rand::thread_rng();
+    let solutions = (0..samples)
+        .filter(|_| goal.is_met_by(&hasher.hash_with_nonce(rng.gen_range(start, end))))
+        .count();
+    ProbeEstimate {
+        sampled: samples,
+        solutions: solutions as u64,
+    }
+}
+
+// the algorithms under which a nonce known to solve the challenge (ex: one
+// the device accepted before) actually meets the goal
+pub fn consistent_algorithms(base: &[u8], goal: &SolveGoal, nonce: Nonce) -> Vec<HashAlgorithm> {
+    HashAlgorithm::ALL
+        .iter()
+        .cloned()
+        .filter(|&algorithm| {
+            goal.is_met_by(
+                &Sha256Hasher::with_algorithm(base.to_vec(), algorithm).hash_with_nonce(nonce),
+            )
+        })
+        .collect()
+}
+
+// searches the first `count` nonces with each algorithm in turn and returns
+// the first algorithm whose candidate `accept` agrees with, ex: the device
+// unlocking with it, along with that candidate
+pub fn detect_algorithm<F>(
+    base: &[u8],
+    goal: &SolveGoal,
+    count: u64,
+    mut accept: F,
+) -> Option<(HashAlgorithm, HashSolution)>
+where
+    F: FnMut(&HashSolution) -> bool,
+{
+    for &algorithm in HashAlgorithm::ALL.iter() {
+        let hasher = Sha256Hasher::with_algorithm(base.to_vec(), algorithm);
+        let candidate = (0..count)
+            .map(|n| (n, hasher.hash_with_nonce(n)))
+            .find(|(_, hash)| goal.is_met_by(hash));
+        if let Some((nonce, hash)) = candidate {
+            let solution = HashSolution {
+                nonce: nonce,
+                nonce_high: 0,
+                attempts: nonce + 1,
+                hash: hash,
+            };
+            if accept(&solution) {
+                return Some((algorithm, solution));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        benchmark_partition, compute_hashrate, consistent_algorithms, detect_algorithm,
+        estimate_hashrate, estimated_completion_time, give_up_reason, lowest_hash, nonce_to_bytes,
+        probe, progress_template, salt_base, HashAlgorithm, HashResponse, HashSolution, HashWorker,
+        HashWorkerFarm, MultiFarm, Nonce, NonceByteOrder, NonceEncoding, NonceWidth,
+        ParseHashError, PartitionScheme, Progress, ProgressStream, RateMeter, Sha256Hash,
+        Sha256Hasher, SolveGoal, SolveOutcome, TNonce, DEFAULT_BATCH_SIZE, DIFFICULTY_1_TARGET,
+    };
+    use crate::ranges::NonceRanges;
+    use rustc_serialize::hex::{FromHex, ToHex};
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::mpsc::channel;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+    #[test]
+    fn it_hashes_a_range_of_nonces() {
+        let hasher = Sha256Hasher::new(b"helloworld".to_vec());
+        let hashes: Vec<(Nonce, Sha256Hash)> = hasher.hash_range(0..3).collect();
+        assert_eq!(
+            hashes,
+            vec![
+                (0, hasher.hash_with_nonce(0)),
+                (1, hasher.hash_with_nonce(1)),
+                (2, hasher.hash_with_nonce(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_encodes_a_hash_as_base64() {
+        let hash = Sha256Hash::from_str(
+            &"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            hash.to_base64(),
+            "ungWv48Bz+pBQUDeXa4iI7ADYaOWF3qctBD/YfIAFa0="
+        );
+    }
+
+    #[test]
+    fn it_creates_sha_hashes_from_hex() {
+        let hash = Sha256Hash::from_str(
+            &"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+        )
+        .unwrap();
+        assert_eq!(
+            Sha256Hash {
+                value: [
+                    0xba, 0x78, 0x16, 0xbf, 0x8f, 0x01, 0xcf, 0xea, 0x41, 0x41, 0x40, 0xde, 0x5d,
+                    0xae, 0x22, 0x23, 0xb0, 0x03, 0x61, 0xa3, 0x96, 0x17, 0x7a, 0x9c, 0xb4, 0x10,
+                    0xff, 0x61, 0xf2, 0x00, 0x15, 0xad,
+                ],
+            },
+            hash
+        );
+    }
+
+    #[test]
+    fn it_round_trips_a_hash_through_bytes() {
+        let hex = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        let mut bytes = [0u8; 32];
+        bytes.copy_from_slice(&hex.from_hex().unwrap());
+        let hash = Sha256Hash::from_bytes(bytes);
+        assert_eq!(hash.to_string(), hex);
+        assert_eq!(hash.as_bytes().to_hex(), hex);
+        assert_eq!(hash, Sha256Hash::from_str(hex).unwrap());
+        assert_eq!(*hash.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn it_verifies_a_nonce_against_a_target() {
+        let hasher = Sha256Hasher::new(b"helloworld".to_vec());
+        let target = Sha256Hash::target_for_hash_attempts_expected(256);
+        let solving = (0..).find(|&n| hasher.hash_with_nonce(n) < target).unwrap();
+        assert!(hasher.verify(solving, &target));
+        let failing = (0..)
+            .find(|&n| hasher.hash_with_nonce(n) >= target)
+            .unwrap();
+        assert!(!hasher.verify(failing, &target));
+    }
+
+    #[test]
+    fn it_fails_to_create_hash_with_wrong_length() {
+        assert_eq!(
+            Sha256Hash::from_str(&"aa00bb".to_string()),
+            Err(ParseHashError::WrongLength {
+                expected: 64,
+                got: 6
+            })
+        );
+    }
+
+    #[test]
+    fn it_fails_to_create_hash_from_invalid_hex() {
+        let hex = format!("{}z", "0".repeat(63));
+        match Sha256Hash::from_str(&hex) {
+            Err(ParseHashError::InvalidHex(_)) => {}
+            other => panic!("Expected invalid hex, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn it_converts_a_hash_from_bytes() {
+        let mut bytes = [0xffu8; 32];
+        bytes[0] = 0x00;
+        assert_eq!(Sha256Hash::from(bytes), Sha256Hash::from_bytes(bytes));
+        assert_eq!(
+            Sha256Hash::try_from(&bytes[..]).unwrap(),
+            Sha256Hash::from_bytes(bytes)
+        );
+        assert_eq!(
+            Sha256Hash::try_from(&bytes[..31]),
+            Err(ParseHashError::WrongLength {
+                expected: 32,
+                got: 31
+            })
+        );
+        let long = [0u8; 33];
+        assert_eq!(
+            Sha256Hash::try_from(&long[..]),
+            Err(ParseHashError::WrongLength {
+                expected: 32,
+                got: 33
+            })
+        );
+        assert_eq!(
+            Sha256Hash::try_from(&long[..]).unwrap_err().to_string(),
+            "Input must be 32 bytes, not 33"
+        );
+    }
+
+    #[test]
+    fn it_serializes_a_hash_as_hex() {
+        let hex = "00000000ffff0000000000000000000000000000000000000000000000000000";
+        let hash = Sha256Hash::from_str(hex).unwrap();
+        let json = serde_json::to_string(&hash).unwrap();
+        assert_eq!(json, format!("\"{}\"", hex));
+        assert_eq!(serde_json::from_str::<Sha256Hash>(&json).unwrap(), hash);
+        let error = serde_json::from_str::<Sha256Hash>("\"aa00bb\"").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("Input must be 64 characters, not 6"));
+    }
+
+    #[test]
+    fn it_hashes_abc() {
         let hasher = Sha256Hasher::new(b"abc".to_vec());
         let answer = Sha256Hash::from_str(
             &"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
@@ -547,6 +2276,17 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_computes_the_probability_of_solving_within_a_budget() {
+        let target = Sha256Hash::target_for_hash_attempts_expected(1000);
+        assert_eq!(target.solve_probability(0), 0.0);
+        assert!(target.solve_probability(1_000_000) > 0.999_999);
+        // 1 - 1/e of solves are done within the expected attempts
+        assert!((target.solve_probability(1000) - 0.632).abs() < 0.001);
+        let target = Sha256Hash::target_for_hash_attempts_expected(1);
+        assert_eq!(target.solve_probability(1), 1.0);
+    }
+
     #[test]
     fn it_computes_hash_targets_for_expected_duration() {
         assert_eq!(
@@ -555,6 +2295,89 @@ mod tests {
         );
     }
 
+    #[test]
+    fn it_counts_leading_zero_bits() {
+        let hash = |hex: &str| Sha256Hash::from_str(hex).unwrap();
+        assert_eq!(
+            hash("ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                .leading_zero_bits(),
+            0
+        );
+        assert_eq!(
+            hash("00f816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                .leading_zero_bits(),
+            8
+        );
+        assert_eq!(
+            hash("000000007f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad")
+                .leading_zero_bits(),
+            33
+        );
+        assert_eq!(
+            hash("0000000000000000000000000000000000000000000000000000000000000000")
+                .leading_zero_bits(),
+            256
+        );
+        // stops at the first set bit, whatever follows
+        assert_eq!(Sha256Hash::from_bytes([0x80; 32]).leading_zero_bits(), 0);
+        let mut bytes = [0xff; 32];
+        bytes[0] = 0x00;
+        bytes[1] = 0x0f;
+        assert_eq!(Sha256Hash::from_bytes(bytes).leading_zero_bits(), 12);
+    }
+
+    #[test]
+    fn it_round_trips_a_difficulty() {
+        for &difficulty in &[1, 2, 1000, 12_345_678, 1 << 40] {
+            assert_eq!(
+                Sha256Hash::target_for_difficulty(difficulty).get_difficulty(),
+                difficulty
+            );
+        }
+        assert_eq!(
+            Sha256Hash::from_str(DIFFICULTY_1_TARGET)
+                .unwrap()
+                .get_difficulty(),
+            1
+        );
+        assert_eq!(Sha256Hash::from_bytes([0xff; 32]).get_difficulty(), 0);
+        assert_eq!(
+            Sha256Hash::from_bytes([0; 32]).get_difficulty(),
+            std::u64::MAX
+        );
+    }
+
+    #[test]
+    fn it_meets_only_targets_strictly_above_the_hash() {
+        let target = Sha256Hash::from_str(
+            &"0000000000000000000000000000000000000000000000000000000000001000".to_string(),
+        )
+        .unwrap();
+        let one_below = Sha256Hash::from_str(
+            &"0000000000000000000000000000000000000000000000000000000000000fff".to_string(),
+        )
+        .unwrap();
+        assert!(one_below.meets_target(&target));
+        assert!(!target.meets_target(&target));
+        assert!(!target.meets_target(&one_below));
+    }
+
+    #[test]
+    fn it_computes_the_margin_below_a_target() {
+        let target = Sha256Hash::from_str(
+            &"0000000000000000000000000000000000000000000000000000000000001000".to_string(),
+        )
+        .unwrap();
+        let hash = Sha256Hash::from_str(
+            &"0000000000000000000000000000000000000000000000000000000000000f00".to_string(),
+        )
+        .unwrap();
+        assert_eq!(hash.margin_below(&target).unwrap().as_u64(), 256);
+        assert_eq!(hash.margin_ratio(&target), Some(0.0625));
+        assert!(target.margin_below(&target).is_none());
+        assert!(target.margin_below(&hash).is_none());
+    }
+
     #[test]
     fn it_computes_expected_hash_attempts_for_target_max() {
         let target = Sha256Hash::from_str(
@@ -572,4 +2395,805 @@ mod tests {
         .unwrap();
         assert_eq!(target.expected_attempts_to_solve(), 4_294_967_296);
     }
+
+    #[test]
+    fn it_saturates_the_attempts_for_very_hard_targets() {
+        // more than u64::MAX attempts are expected for this one
+        let target = Sha256Hash::from_str(
+            &"00000000000000000fffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+        )
+        .unwrap();
+        assert_eq!(target.expected_attempts_to_solve(), std::u64::MAX);
+        assert_eq!(target.p90_attempts_to_solve(), std::u64::MAX);
+        assert_eq!(target.p99_attempts_to_solve(), std::u64::MAX);
+        let zero = Sha256Hash::from_bytes([0; 32]);
+        assert_eq!(zero.expected_attempts_to_solve(), std::u64::MAX);
+
+        // setting up the progress for it doesn't panic either
+        let mut farm = HashWorkerFarm::new(b"helloworld".to_vec(), target, 1);
+        farm.set_progress_stream(ProgressStream::Hidden);
+        farm.set_max_attempts(Some(1000));
+        let outcome = HashWorkerFarm::solve_outcome(Box::from(farm));
+        assert_eq!(outcome.reason(), Some("max_attempts"));
+    }
+
+    #[test]
+    fn it_finds_the_lowest_hash_in_a_range() {
+        let base = b"helloworld";
+        let hasher = Sha256Hasher::new(base.to_vec());
+        let result = lowest_hash(base, 10, 500).unwrap();
+        assert_eq!(result.attempts, 500);
+        assert_eq!(result.hash, hasher.hash_with_nonce(result.nonce));
+        assert!(result.nonce >= 10 && result.nonce < 510);
+        for n in 10..510 {
+            assert!(result.hash <= hasher.hash_with_nonce(n));
+        }
+
+        assert!(lowest_hash(base, 10, 0).is_none());
+        // only the three nonces up to the last one exist
+        let result = lowest_hash(base, std::u64::MAX - 2, 500).unwrap();
+        assert_eq!(result.attempts, 3);
+        assert!(result.nonce >= std::u64::MAX - 2);
+        assert_eq!(lowest_hash(base, std::u64::MAX, 1).unwrap().attempts, 1);
+    }
+
+    #[test]
+    fn it_finds_different_nonces_for_salted_runs() {
+        let target = Sha256Hash::target_for_hash_attempts_expected(1000);
+        let first_solution = |base: Vec<u8>| -> Nonce {
+            let hasher = Sha256Hasher::new(base);
+            (0..).find(|&n| hasher.hash_with_nonce(n) < target).unwrap()
+        };
+        assert_eq!(salt_base(b"helloworld", 0), b"helloworld0".to_vec());
+        assert_ne!(
+            first_solution(salt_base(b"helloworld", 0)),
+            first_solution(salt_base(b"helloworld", 1))
+        );
+    }
+
+    #[test]
+    fn it_solves_with_a_small_worker_stack() {
+        let target = Sha256Hash::target_for_hash_attempts_expected(100);
+        let mut farm = HashWorkerFarm::new(b"helloworld".to_vec(), target.clone(), 2);
+        farm.set_worker_stack_size(32 * 1024);
+        let solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        assert!(solution.hash < target);
+    }
+
+    #[test]
+    fn it_checkpoints_a_rising_resume_point() {
+        let path = std::env::temp_dir().join(format!("pow_key_checkpoint_{}", std::process::id()));
+        let impossible = Sha256Hash::from_str(
+            &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+        )
+        .unwrap();
+        let mut farm = HashWorkerFarm::new(b"helloworld".to_vec(), impossible, 2);
+        farm.set_progress_stream(ProgressStream::Hidden);
+        farm.set_checkpoint_path(Some(path.clone()));
+        let solve = std::thread::spawn(move || {
+            Box::from(farm).solve_with_deadline(Duration::from_millis(2000))
+        });
+        let mut resume_points = vec![];
+        for _ in 0..4 {
+            std::thread::sleep(Duration::from_millis(400));
+            if let Ok(contents) = std::fs::read_to_string(&path) {
+                let searched = NonceRanges::from_str(&contents).unwrap();
+                resume_points.push(searched.next_uncovered(0));
+            }
+        }
+        assert!(solve.join().unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+        assert!(resume_points.len() >= 2);
+        for pair in resume_points.windows(2) {
+            assert!(pair[0] <= pair[1]);
+        }
+        assert!(resume_points.first() < resume_points.last());
+    }
+
+    #[test]
+    fn it_hashes_twice_with_double_sha256() {
+        let answer = Sha256Hash::from_str(
+            &"4f8b42c22dd3729b519ba6f68d2da7cc5b2d606d05daed5ad5128cc03e6c6358".to_string(),
+        )
+        .unwrap();
+        let algorithm = "sha256d".parse::<HashAlgorithm>().unwrap();
+        assert_eq!(algorithm, HashAlgorithm::DoubleSha256);
+        assert_eq!(algorithm.hash(b"abc"), answer);
+        assert_eq!(
+            HashAlgorithm::Sha256.hash(b"abc"),
+            Sha256Hasher::hash_bytes(b"abc")
+        );
+        assert!("sha512".parse::<HashAlgorithm>().is_err());
+    }
+
+    #[test]
+    fn it_hashes_a_file() {
+        let path = std::env::temp_dir().join(format!("pow_key_hash_{}", std::process::id()));
+        std::fs::write(&path, b"abc").unwrap();
+        let hash = Sha256Hasher::hash_reader(std::fs::File::open(&path).unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let answer = Sha256Hash::from_str(
+            &"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+        )
+        .unwrap();
+        assert_eq!(answer, hash);
+    }
+
+    #[test]
+    fn it_solves_for_a_hash_prefix() {
+        let goal = SolveGoal::prefix_from_hex("00").unwrap();
+        let farm = HashWorkerFarm::new_with_goal(b"helloworld".to_vec(), goal, 2);
+        let solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        assert_eq!(solution.hash.value[0], 0x00);
+        let hasher = Sha256Hasher::new(b"helloworld".to_vec());
+        assert_eq!(solution.hash, hasher.hash_with_nonce(solution.nonce));
+    }
+
+    #[test]
+    fn it_solves_for_a_custom_predicate() {
+        let solution = HashWorkerFarm::solve_predicate(
+            b"helloworld".to_vec(),
+            |hash: &Sha256Hash| hash.value[31] == 0x00,
+            2,
+        )
+        .unwrap();
+        assert_eq!(solution.hash.value[31], 0x00);
+        let hasher = Sha256Hasher::new(b"helloworld".to_vec());
+        assert_eq!(solution.hash, hasher.hash_with_nonce(solution.nonce));
+    }
+
+    #[test]
+    fn it_stops_every_worker_once_solved() {
+        let farm = HashWorkerFarm::new(
+            b"helloworld".to_vec(),
+            Sha256Hash::target_for_hash_attempts_expected(1000),
+            8,
+        );
+        // each worker thread holds the flag until it exits
+        let stop = farm.stop.clone();
+        assert!(HashWorkerFarm::solve(Box::from(farm)).is_some());
+        assert!(stop.load(Ordering::Relaxed));
+        let start = Instant::now();
+        while Arc::strong_count(&stop) > 1 {
+            assert!(start.elapsed() < Duration::from_secs(5));
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn it_gives_up_at_the_deadline() {
+        // practically impossible; all zeros would leave no expected attempts
+        let impossible = Sha256Hash::from_str(
+            &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+        )
+        .unwrap();
+        let farm = HashWorkerFarm::new(b"helloworld".to_vec(), impossible, 2);
+        let start = Instant::now();
+        assert!(Box::from(farm)
+            .solve_with_deadline(Duration::from_secs(1))
+            .is_none());
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_secs(1) && elapsed < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn it_reports_progress_to_a_callback() {
+        let impossible = Sha256Hash::from_str(
+            &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+        )
+        .unwrap();
+        let mut farm = HashWorkerFarm::new(b"helloworld".to_vec(), impossible, 1);
+        farm.set_deadline(Some(Duration::from_millis(1500)));
+        let mut reports: Vec<Progress> = vec![];
+        let outcome = Box::from(farm).solve_with_callback(|progress| reports.push(progress));
+        assert_eq!(outcome.reason(), Some("timeout"));
+        assert!(!reports.is_empty());
+        for pair in reports.windows(2) {
+            assert!(pair[1].attempts >= pair[0].attempts);
+            assert!(pair[1].elapsed > pair[0].elapsed);
+        }
+    }
+
+    #[test]
+    fn it_stops_the_hashrate_test_when_interrupted() {
+        let mut farm = HashWorkerFarm::new_test(1);
+        farm.set_progress_stream(ProgressStream::Hidden);
+        let interrupt = Arc::new(AtomicBool::new(false));
+        farm.set_interrupt(interrupt.clone());
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(500));
+            interrupt.store(true, Ordering::SeqCst);
+        });
+        let start = Instant::now();
+        farm.run_test(60);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn it_stops_when_interrupted() {
+        let impossible = Sha256Hash::from_str(
+            &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+        )
+        .unwrap();
+        let mut farm = HashWorkerFarm::new(b"helloworld".to_vec(), impossible, 2);
+        farm.set_progress_stream(ProgressStream::Hidden);
+        let interrupt = Arc::new(AtomicBool::new(false));
+        farm.set_interrupt(interrupt.clone());
+        let second_worker_start = farm.workers[1].start_nonce;
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(500));
+            interrupt.store(true, Ordering::SeqCst);
+        });
+        let start = Instant::now();
+        match HashWorkerFarm::solve_outcome(Box::from(farm)) {
+            SolveOutcome::Interrupted {
+                attempts,
+                highest_nonce,
+            } => {
+                assert!(attempts > 0);
+                assert!(highest_nonce > second_worker_start);
+            }
+            outcome => panic!("Expected to be interrupted, not {}", outcome),
+        }
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn it_estimates_the_hashrate() {
+        // the CLI asks for at least 20 seconds; a short run still measures something
+        assert!(estimate_hashrate(1, 1) > 0);
+    }
+
+    #[test]
+    fn it_hashes_a_128_bit_nonce() {
+        let mut hasher = Sha256Hasher::new(b"helloworld".to_vec());
+        hasher.set_nonce_width(NonceWidth::Bits128);
+        let mut message = b"helloworld".to_vec();
+        message.extend_from_slice(&[0; 16]);
+        assert_eq!(
+            hasher.hash_with_nonce(0),
+            Sha256Hasher::hash_bytes(&message)
+        );
+        assert_eq!(
+            hasher.hash_with_wide_nonce(0),
+            Sha256Hasher::hash_bytes(&message)
+        );
+        // the default is unchanged
+        let hasher = Sha256Hasher::new(b"helloworld".to_vec());
+        assert_eq!(
+            hasher.hash_with_nonce(0),
+            Sha256Hasher::hash_bytes(b"helloworld\0\0\0\0\0\0\0\0")
+        );
+    }
+
+    #[test]
+    fn it_solves_with_128_bit_nonces() {
+        let mut farm = HashWorkerFarm::new(
+            b"helloworld".to_vec(),
+            Sha256Hash::target_for_hash_attempts_expected(1000),
+            3,
+        );
+        farm.set_nonce_width(NonceWidth::Bits128);
+        let solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        let mut hasher = Sha256Hasher::new(b"helloworld".to_vec());
+        hasher.set_nonce_width(NonceWidth::Bits128);
+        assert!(solution.nonce_high < 3);
+        assert_eq!(
+            hasher.hash_with_wide_nonce(solution.wide_nonce()),
+            solution.hash
+        );
+    }
+
+    #[test]
+    fn it_counts_the_solving_attempt() {
+        let base = b"helloworld".to_vec();
+        let target = Sha256Hash::target_for_hash_attempts_expected(1000);
+        let hasher = Sha256Hasher::new(base.clone());
+        let first = (0..).find(|&n| hasher.hash_with_nonce(n) < target).unwrap();
+        // one worker searches from 0 upward, hashing every nonce up to the solution
+        let farm = HashWorkerFarm::new(base, target, 1);
+        let solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        assert_eq!(solution.nonce, first);
+        assert_eq!(solution.attempts, first + 1);
+    }
+
+    #[test]
+    fn it_solves_quietly() {
+        let mut farm = HashWorkerFarm::new(
+            b"helloworld".to_vec(),
+            Sha256Hash::target_for_hash_attempts_expected(1000),
+            2,
+        );
+        farm.set_progress_stream(ProgressStream::Hidden);
+        let solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        assert!(solution.hash < Sha256Hash::target_for_hash_attempts_expected(1000));
+    }
+
+    #[test]
+    fn it_computes_an_equivalent_target_for_a_prefix() {
+        let goal = SolveGoal::prefix_from_hex("abcd").unwrap();
+        assert_eq!(goal.equivalent_target().expected_attempts_to_solve(), 65536);
+    }
+
+    #[test]
+    fn it_computes_a_finite_hashrate_in_under_a_second() {
+        assert_eq!(compute_hashrate(500, Duration::from_millis(500)), 1000.0);
+        assert_eq!(compute_hashrate(3000, Duration::from_secs(2)), 1500.0);
+        assert_eq!(compute_hashrate(500, Duration::from_secs(0)), 0.0);
+    }
+
+    #[test]
+    fn it_estimates_the_completion_time() {
+        let now = UNIX_EPOCH + Duration::from_secs(1000);
+        assert_eq!(
+            estimated_completion_time(500, 10.0, now),
+            Some(UNIX_EPOCH + Duration::from_secs(1050))
+        );
+        assert_eq!(estimated_completion_time(0, 10.0, now), None);
+        assert_eq!(estimated_completion_time(500, 0.0, now), None);
+        assert_eq!(
+            estimated_completion_time(500, std::f64::INFINITY, now),
+            None
+        );
+    }
+
+    #[test]
+    fn it_has_no_completion_time_past_year_9999() {
+        let target = Sha256Hash::from_str(
+            &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+        )
+        .unwrap();
+        let completion = estimated_completion_time(
+            target.expected_attempts_to_solve(),
+            1000.0,
+            SystemTime::now(),
+        );
+        assert_eq!(completion, None);
+    }
+
+    #[test]
+    fn it_stops_at_the_attempt_limit() {
+        let target = Sha256Hash::from_str(
+            &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+        )
+        .unwrap(); // practically impossible to solve
+        let mut farm = HashWorkerFarm::new(b"helloworld".to_vec(), target, 2);
+        farm.set_max_attempts(Some(1000));
+        let outcome = HashWorkerFarm::solve_outcome(Box::from(farm));
+        assert_eq!(outcome.reason(), Some("max_attempts"));
+    }
+
+    #[test]
+    fn it_reports_an_exhausted_range() {
+        let target = Sha256Hash::from_str(
+            &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+        )
+        .unwrap(); // practically impossible to solve
+        let farm = HashWorkerFarm::resume(
+            b"helloworld".to_vec(),
+            SolveGoal::Target(target),
+            2,
+            NonceRanges::outside(0, 200),
+        );
+        let outcome = HashWorkerFarm::solve_outcome(Box::from(farm));
+        assert_eq!(outcome.reason(), Some("exhausted"));
+    }
+
+    #[test]
+    fn it_starts_the_first_worker_at_the_offset() {
+        let target = Sha256Hash::target_for_hash_attempts_expected(100);
+        let start = 1_000_000;
+        let farm = HashWorkerFarm::new_starting_at(b"helloworld".to_vec(), target, 4, start);
+        assert_eq!(farm.workers[0].start_nonce, start);
+        let share = (std::u64::MAX - start) / 4;
+        for (i, worker) in farm.workers.iter().enumerate() {
+            assert_eq!(worker.start_nonce, start + share * i as u64);
+        }
+        assert_eq!(farm.workers[3].end_nonce, std::u64::MAX);
+    }
+
+    #[test]
+    fn it_resumes_a_checkpoint_on_a_different_number_of_workers() {
+        let target = Sha256Hash::target_for_hash_attempts_expected(100);
+        let original = HashWorkerFarm::new(b"helloworld".to_vec(), target.clone(), 4);
+        let mut covered = NonceRanges::new();
+        for (i, worker) in original.workers.iter().enumerate() {
+            covered.insert(
+                worker.start_nonce,
+                worker.start_nonce + 1000 * (i as u64 + 1),
+            );
+        }
+
+        let resumed = HashWorkerFarm::resume(
+            b"helloworld".to_vec(),
+            SolveGoal::Target(target),
+            3,
+            covered.clone(),
+        );
+        assert_eq!(resumed.workers.len(), 3);
+        let mut searched = covered.clone();
+        let mut next_start = covered.next_uncovered(0);
+        for worker in &resumed.workers {
+            assert_eq!(worker.start_nonce, next_start);
+            searched.insert(worker.start_nonce, worker.end_nonce);
+            next_start = worker.end_nonce;
+        }
+        assert_eq!(searched.ranges(), &[(0, std::u64::MAX)]);
+
+        let uncovered = |w: usize| {
+            covered.uncovered_count(resumed.workers[w].start_nonce, resumed.workers[w].end_nonce)
+        };
+        assert_eq!(
+            uncovered(0) + uncovered(1) + uncovered(2),
+            std::u64::MAX - 10_000
+        );
+        assert_eq!(uncovered(0), uncovered(1));
+    }
+
+    #[test]
+    fn it_estimates_the_solution_density_of_a_range() {
+        let lenient = SolveGoal::Target(
+            Sha256Hash::from_str(
+                &"0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+            )
+            .unwrap(),
+        );
+        let estimate = probe(b"helloworld", &lenient, 0, 1_000_000, 2_000);
+        assert_eq!(estimate.sampled, 2_000);
+        assert!(estimate.density() > 0.0);
+        assert!(estimate.expected_solutions(1_000_000) > 0.0);
+
+        let impossible = SolveGoal::Target(
+            Sha256Hash::from_str(
+                &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+            )
+            .unwrap(),
+        );
+        let estimate = probe(b"helloworld", &impossible, 0, 1_000_000, 2_000);
+        assert_eq!(estimate.density(), 0.0);
+    }
+
+    #[test]
+    fn it_detects_a_double_sha256_challenge() {
+        let base = b"helloworld".to_vec();
+        let goal = SolveGoal::Target(
+            Sha256Hash::from_str(
+                &"00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+            )
+            .unwrap(),
+        );
+        // stands in for a device that hashes with double SHA-256
+        let device = Sha256Hasher::with_algorithm(base.clone(), HashAlgorithm::DoubleSha256);
+        let (algorithm, solution) = detect_algorithm(&base, &goal, 10_000, |candidate| {
+            goal.is_met_by(&device.hash_with_nonce(candidate.nonce))
+        })
+        .unwrap();
+        assert_eq!(algorithm, HashAlgorithm::DoubleSha256);
+        assert_eq!(solution.hash, device.hash_with_nonce(solution.nonce));
+        assert_eq!(
+            consistent_algorithms(&base, &goal, solution.nonce),
+            vec![HashAlgorithm::DoubleSha256]
+        );
+    }
+
+    #[test]
+    fn it_reports_a_non_increasing_best_hash() {
+        let base = b"helloworld".to_vec();
+        let (sender, receiver) = channel();
+        let worker = HashWorker {
+            id: 0,
+            start_nonce: 0,
+            end_nonce: 5_000,
+            stride: 1,
+            covered: Arc::new(NonceRanges::new()),
+            hasher: Sha256Hasher::new(base.clone()),
+            out_handle: sender,
+            predicate: Arc::new(|hash: &Sha256Hash| hash.value.starts_with(&[0, 0, 0, 0])),
+            stop: Arc::new(AtomicBool::new(false)),
+            batch_size: DEFAULT_BATCH_SIZE,
+        };
+        worker.solve();
+        drop(worker);
+        let best: Vec<Sha256Hash> = receiver
+            .iter()
+            .filter_map(|response| match response {
+                HashResponse::Best(hash) => Some(hash),
+                _ => None,
+            })
+            .collect();
+        assert!(best.len() > 1);
+        for pair in best.windows(2) {
+            assert!(pair[1] <= pair[0]);
+        }
+        assert_eq!(
+            *best.last().unwrap(),
+            lowest_hash(&base, 0, 5_000).unwrap().hash
+        );
+    }
+
+    #[cfg(feature = "affinity")]
+    #[test]
+    fn it_assigns_workers_to_cores_in_turn() {
+        use super::core_assignments;
+        assert_eq!(core_assignments(6, 4), vec![0, 1, 2, 3, 0, 1]);
+        assert_eq!(core_assignments(2, 8), vec![0, 1]);
+        assert_eq!(core_assignments(3, 0), Vec::<usize>::new());
+    }
+
+    #[test]
+    fn it_counts_the_same_attempts_in_batches() {
+        let misses = |batch_size: u64| {
+            let (sender, receiver) = channel();
+            let worker = HashWorker {
+                id: 0,
+                start_nonce: 0,
+                end_nonce: 10_000,
+                stride: 1,
+                covered: Arc::new(NonceRanges::new()),
+                hasher: Sha256Hasher::new(b"helloworld".to_vec()),
+                out_handle: sender,
+                predicate: Arc::new(|_: &Sha256Hash| false),
+                stop: Arc::new(AtomicBool::new(false)),
+                batch_size: batch_size,
+            };
+            worker.solve();
+            drop(worker);
+            receiver
+                .iter()
+                .filter_map(|response| match response {
+                    HashResponse::Miss(_, count, nonce) => Some((count, nonce)),
+                    _ => None,
+                })
+                .collect::<Vec<(u64, Nonce)>>()
+        };
+        let single = misses(1);
+        let batched = misses(DEFAULT_BATCH_SIZE);
+        assert_eq!(single.len(), 10_000);
+        assert_eq!(batched.len(), 3);
+        for batches in &[single, batched] {
+            assert_eq!(batches.iter().map(|&(count, _)| count).sum::<u64>(), 10_000);
+            assert_eq!(batches.last().unwrap().1, 9_999);
+        }
+    }
+
+    #[test]
+    fn it_encodes_a_nonce_for_a_device() {
+        let nonce: Nonce = 0x0102_0304;
+        let encode = |encoding: &str| {
+            encoding
+                .parse::<NonceEncoding>()
+                .unwrap()
+                .encode(nonce)
+                .unwrap()
+        };
+        assert_eq!(encode("le8"), vec![4, 3, 2, 1, 0, 0, 0, 0]);
+        assert_eq!(encode("be8"), vec![0, 0, 0, 0, 1, 2, 3, 4]);
+        assert_eq!(encode("le4"), vec![4, 3, 2, 1]);
+        assert_eq!(encode("be4"), vec![1, 2, 3, 4]);
+        // le8 is the layout hashed while solving
+        assert_eq!(
+            nonce.as_hex_bytes(NonceByteOrder::Little),
+            "0403020100000000"
+        );
+        assert!(NonceEncoding::Le4.encode(1 << 32).is_err());
+    }
+
+    #[test]
+    fn it_writes_a_nonce_in_either_byte_order() {
+        let nonce: Nonce = 0x0102_0304_0506_0708;
+        assert_eq!(
+            nonce_to_bytes(nonce, NonceByteOrder::Little),
+            [8, 7, 6, 5, 4, 3, 2, 1]
+        );
+        assert_eq!(
+            nonce_to_bytes(nonce, NonceByteOrder::Big),
+            [1, 2, 3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(
+            nonce.as_hex_bytes(NonceByteOrder::default()),
+            "0807060504030201"
+        );
+        assert_eq!(nonce.as_hex_bytes(NonceByteOrder::Big), "0102030405060708");
+
+        // the hasher hashes the same bytes that are sent to the lock
+        let mut hasher = Sha256Hasher::new(b"helloworld".to_vec());
+        hasher.set_nonce_byte_order(NonceByteOrder::Big);
+        let mut message = b"helloworld".to_vec();
+        message.extend_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(
+            hasher.hash_with_nonce(nonce),
+            Sha256Hasher::hash_bytes(&message)
+        );
+    }
+
+    #[test]
+    fn it_tests_the_hashrate_with_the_given_base() {
+        let farm = HashWorkerFarm::new_test_with_base(vec![b'x'; 300], 2);
+        assert_eq!(farm.workers.len(), 2);
+        for worker in &farm.workers {
+            assert_eq!(worker.hasher.base.len(), 300);
+        }
+    }
+
+    #[test]
+    fn it_finds_the_same_first_solution_with_either_partition() {
+        let base = b"helloworld".to_vec();
+        let goal = SolveGoal::Target(Sha256Hash::target_for_hash_attempts_expected(256));
+        let hasher = Sha256Hasher::new(base.clone());
+        let first = (0..)
+            .find(|&n| goal.is_met_by(&hasher.hash_with_nonce(n)))
+            .unwrap();
+        // only the first solution is accepted
+        for &partition in &[PartitionScheme::Contiguous, PartitionScheme::Interleaved] {
+            let (solution, _) = benchmark_partition(&base, &goal, 2, partition, first + 1);
+            let solution = solution.unwrap();
+            assert_eq!(solution.nonce, first);
+            // the worker that found it searched all of its nonces below it first
+            assert!(solution.attempts * 2 >= first);
+        }
+    }
+
+    #[test]
+    fn it_interleaves_worker_nonces() {
+        let mut farm = HashWorkerFarm::new(
+            b"helloworld".to_vec(),
+            Sha256Hash::target_for_hash_attempts_expected(100),
+            3,
+        );
+        farm.set_partition(PartitionScheme::Interleaved);
+        let worker = &farm.workers[1];
+        assert_eq!(worker.next_nonce(worker.start_nonce), Some(1));
+        assert_eq!(worker.next_nonce(2), Some(4));
+        assert_eq!(worker.next_nonce(4), Some(4));
+        assert_eq!(farm.searched(&[6, 4, 5]).ranges(), &[(0, 4)]);
+    }
+
+    #[test]
+    fn it_counts_every_attempt_of_an_exhausted_range() {
+        let farm = HashWorkerFarm::resume(
+            b"helloworld".to_vec(),
+            SolveGoal::Target(Sha256Hash::from_bytes([0u8; 32])), // impossible to solve
+            3,
+            NonceRanges::outside(0, 30),
+        );
+        farm.spawn_workers();
+        let mut attempts = 0;
+        let mut completed_workers = 0;
+        for response in farm.reply_handle.iter() {
+            if let HashResponse::NoSolution(_, count) = response {
+                attempts += count;
+                completed_workers += 1;
+                if completed_workers == 3 {
+                    break;
+                }
+            }
+        }
+        assert_eq!(attempts, 30);
+    }
+
+    #[test]
+    fn it_covers_the_same_nonces_with_either_partition() {
+        for &partition in &[PartitionScheme::Contiguous, PartitionScheme::Interleaved] {
+            let mut farm = HashWorkerFarm::resume(
+                b"helloworld".to_vec(),
+                SolveGoal::Target(Sha256Hash::target_for_hash_attempts_expected(100)),
+                3,
+                NonceRanges::outside(0, 30),
+            );
+            farm.set_partition(partition);
+            let mut nonces = vec![];
+            for worker in &farm.workers {
+                let mut next = worker.next_nonce(worker.start_nonce);
+                while let Some(n) = next {
+                    nonces.push(n);
+                    next = worker.next_nonce(n + 1);
+                }
+            }
+            nonces.sort();
+            assert_eq!(nonces, (0..30).collect::<Vec<Nonce>>());
+        }
+    }
+
+    #[test]
+    fn it_solves_the_easiest_of_several_challenges_first() {
+        let impossible = SolveGoal::Target(
+            Sha256Hash::from_str(
+                &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+            )
+            .unwrap(),
+        );
+        let easy = SolveGoal::Target(Sha256Hash::target_for_hash_attempts_expected(100));
+        let farm = MultiFarm::new(
+            vec![
+                (b"hard".to_vec(), impossible),
+                (b"easy".to_vec(), easy.clone()),
+            ],
+            3,
+        );
+        assert_eq!(farm.worker_shares(), vec![2, 1]);
+        let (challenge, solution) = farm.solve().unwrap();
+        assert_eq!(challenge, 1);
+        let hash = Sha256Hasher::new(b"easy".to_vec()).hash_with_nonce(solution.nonce);
+        assert_eq!(solution.hash, hash);
+        assert!(easy.is_met_by(&hash));
+    }
+
+    #[test]
+    fn it_stops_forwarding_once_a_challenge_is_solved() {
+        // chosen so neither worker has anything more to send before it
+        // stops: c3924's lowest hash is at nonce 1, and c194 is solved only
+        // at nonce 298, after its lowest miss
+        let impossible = SolveGoal::Target(Sha256Hash::from_bytes([0; 32]));
+        let easy = SolveGoal::Target(Sha256Hash::target_for_hash_attempts_expected(2000));
+        let farm = MultiFarm::new(
+            vec![(b"c3924".to_vec(), impossible), (b"c194".to_vec(), easy)],
+            2,
+        );
+        let (solution, forwarders) = farm.solve_with_forwarders();
+        assert!(solution.is_some());
+        let (sender, receiver) = channel();
+        std::thread::spawn(move || {
+            for forwarder in forwarders {
+                forwarder.join().unwrap();
+            }
+            sender.send(()).unwrap();
+        });
+        assert!(receiver.recv_timeout(Duration::from_secs(10)).is_ok());
+    }
+
+    #[test]
+    fn it_excludes_warmup_attempts_from_the_hashrate() {
+        let start = Instant::now();
+        let mut meter = RateMeter::new(start, Duration::from_secs(5));
+        meter.attempts += 100;
+        meter.tick(start + Duration::from_secs(1));
+        assert_eq!(meter.measured(start + Duration::from_secs(1)), None);
+        meter.attempts += 50;
+        meter.tick(start + Duration::from_secs(5));
+        meter.attempts += 30;
+        meter.tick(start + Duration::from_secs(6));
+        assert_eq!(meter.attempts, 30);
+        assert_eq!(
+            meter.measured(start + Duration::from_secs(7)),
+            Some(Duration::from_secs(2))
+        );
+    }
+
+    #[test]
+    fn it_gives_up_when_projected_to_be_too_slow() {
+        let second = Duration::from_secs(1);
+        // 1000 H/s with 1m attempts to go is about 17 minutes
+        let reason = give_up_reason(1_001_000, 1000, second, Duration::from_secs(600)).unwrap();
+        assert!(reason.starts_with("Giving up: the solve is projected to take"));
+        assert_eq!(
+            give_up_reason(1_001_000, 1000, second, Duration::from_secs(3600)),
+            None
+        );
+    }
+
+    #[test]
+    fn it_leaves_color_out_of_the_template() {
+        assert!(progress_template(40, true).contains(".green"));
+        let template = progress_template(40, false);
+        assert!(!template.contains(".green"));
+        assert!(!template.contains("spinner:"));
+        assert!(template.contains("{bar:40}"));
+    }
+
+    #[test]
+    fn it_displays_a_solution() {
+        let solution = HashSolution {
+            nonce: 1,
+            nonce_high: 0,
+            attempts: 42,
+            hash: Sha256Hash::from_bytes([0; 32]),
+        };
+        let text = solution.to_string();
+        assert!(text.contains("Solved with nonce: 1,"));
+        assert!(text.contains("As bytes: 0100000000000000,"));
+        assert!(text.contains("Leading zero bits: 256"));
+        assert!(text.contains("Attempts: 42"));
+        let big = solution.display_with_order(NonceByteOrder::Big).to_string();
+        assert!(big.contains("As bytes: 0000000000000001,"));
+    }
 }
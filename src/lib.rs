@@ -0,0 +1,11 @@
+pub mod gpu;
+pub mod hash;
+pub mod net;
+pub mod ranges;
+#[cfg(feature = "serve")]
+pub mod serve;
+pub mod session;
+pub mod state;
+pub mod target;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
@@ -1,16 +1,189 @@
 mod cli;
-mod hash;
-mod net;
 
-use crate::hash::Sha256Hash;
-use crate::net::PowServer;
-use clap::{value_t, App, AppSettings, Arg, SubCommand};
+use clap::{value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
+use log::error;
+use pow_key::hash::{
+    ColorChoice, HashAlgorithm, NonceByteOrder, NonceEncoding, PartitionScheme, ProgressStream,
+    Sha256Hash, SolveGoal,
+};
+use pow_key::net::LockStatus;
+use pow_key::ranges::NonceRanges;
+use pow_key::state::SolveState;
+use pow_key::target::{self, TargetSpec};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+const TARGET_ARG_NAMES: [&str; 6] = [
+    "target",
+    "difficulty",
+    "leading zeros",
+    "nbits",
+    "duration",
+    "confidence",
+];
+
+// the arguments that each describe the whole target on their own
+const TARGET_SOURCES: [&str; 5] = ["target", "difficulty", "leading zeros", "nbits", "duration"];
+
+fn other_target_sources(name: &str) -> Vec<&'static str> {
+    TARGET_SOURCES
+        .iter()
+        .cloned()
+        .filter(|&source| source != name)
+        .collect()
+}
+
+// the arguments for every way of describing a target; commands that need a
+// target take exactly one of them (duration and confidence also need a hashrate)
+fn target_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("target")
+            .short("t")
+            .long("target")
+            .help("the hex representation of the sha256 hash the solution hash must be less than")
+            .takes_value(true)
+            .conflicts_with_all(&other_target_sources("target")),
+        Arg::with_name("difficulty")
+            .long("difficulty")
+            .help("a bitcoin style difficulty")
+            .takes_value(true)
+            .conflicts_with_all(&other_target_sources("difficulty")),
+        Arg::with_name("leading zeros")
+            .long("leading-zeros")
+            .help("the number of leading zero bits the solution hash must have")
+            .takes_value(true)
+            .conflicts_with_all(&other_target_sources("leading zeros")),
+        Arg::with_name("nbits")
+            .long("nbits")
+            .help("a bitcoin style compact target in hex, ex: 1d00ffff")
+            .takes_value(true)
+            .conflicts_with_all(&other_target_sources("nbits")),
+        Arg::with_name("duration")
+            .short("d")
+            .long("duration")
+            .help("a plain text description of how long it should take to solve, ex: 4hr 25min, at --hashrate")
+            .takes_value(true)
+            .conflicts_with_all(&other_target_sources("duration")),
+        Arg::with_name("hashrate")
+            .short("r")
+            .long("hashrate")
+            .help("the hashrate in hashes per second")
+            .takes_value(true),
+        Arg::with_name("confidence")
+            .long("confidence")
+            .help("the probability, between 0 and 1, of solving within the duration")
+            .takes_value(true)
+            .requires_all(&["duration", "hashrate"]),
+    ]
+}
+
+fn target_spec(matches: &ArgMatches) -> TargetSpec {
+    if let Some(target) = matches.value_of("target") {
+        return TargetSpec::Hex(target.to_string());
+    }
+    if matches.is_present("difficulty") {
+        return TargetSpec::Difficulty(
+            value_t!(matches, "difficulty", u64).expect("Expected a valid integer difficulty"),
+        );
+    }
+    if matches.is_present("leading zeros") {
+        return TargetSpec::LeadingZeroBits(
+            value_t!(matches, "leading zeros", u32).expect("Expected a valid number of bits"),
+        );
+    }
+    if let Some(n_bits) = matches.value_of("nbits") {
+        return TargetSpec::NBits(
+            u32::from_str_radix(n_bits, 16).expect("Expected a valid hex nbits"),
+        );
+    }
+    let duration = matches
+        .value_of("duration")
+        .expect("Expected a target, difficulty, leading zeros, nbits or duration")
+        .to_string();
+    let hash_rate = value_t!(matches, "hashrate", u64)
+        .expect("Expected a valid integer hashrate with --duration");
+    match matches.is_present("confidence") {
+        true => TargetSpec::Confidence {
+            confidence: value_t!(matches, "confidence", f64).expect("Invalid confidence"),
+            duration: duration,
+            hash_rate: hash_rate,
+        },
+        false => TargetSpec::Duration {
+            duration: duration,
+            hash_rate: hash_rate,
+        },
+    }
+}
+
+// the base given with --base or --base-file
+fn solve_base(matches: &ArgMatches) -> Vec<u8> {
+    match matches.value_of("base file") {
+        Some(path) => std::fs::read(path).expect("Unable to read base file"),
+        None => cli::read_base_arg(
+            matches
+                .value_of("base string")
+                .expect("Expected a base string"),
+            std::io::stdin(),
+        )
+        .expect("Invalid base"),
+    }
+}
+
+fn num_workers(matches: &ArgMatches) -> u8 {
+    let cores = std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1);
+    cli::parse_worker_count(
+        matches
+            .value_of("number of processes")
+            .expect("Expected a number of worker processes"),
+        cores,
+    )
+    .expect("Invalid number of worker processes")
+}
+
+// how the lock hashes the base and nonce, for the commands that hash them
+fn algo_arg<'a, 'b>() -> Arg<'a, 'b> {
+    Arg::with_name("algo")
+        .long("algo")
+        .help("how the lock hashes the base and nonce")
+        .takes_value(true)
+        .possible_values(&[
+            "sha256",
+            "sha256d",
+            "sha256-nonce-first",
+            "sha256d-nonce-first",
+        ])
+        .default_value("sha256")
+}
+
+fn algorithm(matches: &ArgMatches) -> HashAlgorithm {
+    value_t!(matches, "algo", HashAlgorithm).expect("Invalid hash algorithm")
+}
+
+fn byte_order(matches: &ArgMatches) -> NonceByteOrder {
+    match matches.is_present("big endian") {
+        true => NonceByteOrder::Big,
+        false => NonceByteOrder::Little,
+    }
+}
 
 fn main() {
+    // diagnostics go to stderr through the log, so stdout is left for results;
+    // RUST_LOG=debug shows more, RUST_LOG=warn less
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
     let matches = App::new("POW Key")
         .version(env!("CARGO_PKG_VERSION"))
         .author("David Walsh <dawalsh@gmail.com>")
         .about("The Proof of Work lock companion app")
+        .arg(
+            Arg::with_name("color")
+                .long("color")
+                .help("when to color the progress bars; auto colors only on a terminal")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto"))
         .subcommand(
             SubCommand::with_name("solve")
                 .about("finds a nonce that will unlock the device")
@@ -18,40 +191,429 @@ fn main() {
                     Arg::with_name("base string")
                         .short("b")
                         .long("base")
-                        .help("the ascii string generated by the device when it was locked")
+                        .help("the ascii string generated by the device when it was locked, @ and a path to read it from a file, or - to read it from stdin")
                         .takes_value(true)
-                        .required(true))
+                        .required_unless_one(&["base file", "batch", "continue", "stdin jsonl", "target from device"]))
                 .arg(
-                    Arg::with_name("target hash")
-                        .short("t")
-                        .long("target")
-                        .help("the hex representation of the sha256 hash the solution hash must be less than")
+                    Arg::with_name("base file")
+                        .long("base-file")
+                        .help("a file whose entire contents are used as the base")
                         .takes_value(true)
-                        .required(true))
+                        .conflicts_with("base string"))
+                .arg(
+                    Arg::with_name("batch")
+                        .long("batch")
+                        .help("a CSV file with a base,target header and one challenge per row, solved in order")
+                        .takes_value(true)
+                        .conflicts_with_all(&["base string", "base file", "prefix"]))
+                .arg(
+                    Arg::with_name("stdin jsonl")
+                        .long("stdin-jsonl")
+                        .help("reads challenges from stdin as JSON lines, ex: {\"base\":\"...\",\"target\":\"...\"}, and writes a JSON result line for each as it's solved")
+                        .conflicts_with_all(&["base string", "base file", "batch", "prefix", "continue", "resume", "save on exit", "checkpoint", "verify against device", "auto algorithm"])
+                        .conflicts_with_all(&TARGET_ARG_NAMES))
+                .args(&target_args())
+                .arg(
+                    Arg::with_name("target from device")
+                        .long("target-from-device")
+                        .help("fetches the base and target from the device at --hostname and --port instead of taking them as arguments")
+                        .requires_all(&["hostname", "port"])
+                        .conflicts_with_all(&["base string", "base file", "batch", "stdin jsonl", "prefix", "continue", "resume token"])
+                        .conflicts_with_all(&TARGET_ARG_NAMES))
+                .arg(
+                    Arg::with_name("min leading zeros")
+                        .long("min-leading-zeros")
+                        .help("also requires the solution hash to have at least this many leading zero bits, for a solution with margin below the target")
+                        .takes_value(true)
+                        .conflicts_with_all(&["prefix", "continue", "resume token", "stdin jsonl"]))
+                .arg(
+                    Arg::with_name("prefix")
+                        .long("prefix")
+                        .help("hex bytes the solution hash must start with; mutually exclusive with --target")
+                        .takes_value(true)
+                        .conflicts_with_all(&TARGET_ARG_NAMES))
                 .arg(
                     Arg::with_name("number of processes")
                     .short("p")
                     .long("num_processes")
-                    .help("the number of worker processes to generate")
+                    .help("the number of worker processes to generate, or a fraction of the cores, ex: 50% or 0.5x, or auto for one per core")
+                    .takes_value(true)
+                    .default_value("1"))
+                .arg(
+                    Arg::with_name("runs")
+                    .long("runs")
+                    .help("the number of times to solve the challenge")
+                    .takes_value(true)
+                    .default_value("1"))
+                .arg(
+                    Arg::with_name("salt each run")
+                    .long("salt-each-run")
+                    .help("appends the run index to the base string so each run solves a distinct challenge of the same difficulty; this changes the effective base"))
+                .arg(
+                    Arg::with_name("notify")
+                    .long("notify")
+                    .help("sends a desktop notification when the solve completes (requires the \"notify\" feature)"))
+                .arg(
+                    Arg::with_name("pin cores")
+                    .long("pin-cores")
+                    .help("keeps each worker thread on its own core (requires the \"affinity\" feature)"))
+                .arg(
+                    Arg::with_name("thread stack size")
+                    .long("thread-stack-kb")
+                    .help("the stack size of each worker thread in KiB")
+                    .takes_value(true)
+                    .default_value("256"))
+                .arg(
+                    Arg::with_name("verify against device")
+                    .long("verify-against-device")
+                    .help("submits each candidate nonce to the device and only reports a solution the device accepts")
+                    .requires_all(&["hostname", "port"]))
+                .arg(
+                    Arg::with_name("max attempts")
+                    .long("max-attempts")
+                    .help("stops without a solution after this many hash attempts across all workers")
+                    .takes_value(true))
+                .arg(
+                    Arg::with_name("checkpoint")
+                    .long("checkpoint")
+                    .help("a file the searched nonce ranges are periodically written to")
+                    .takes_value(true))
+                .arg(
+                    Arg::with_name("resume")
+                    .long("resume")
+                    .help("a checkpoint file from an earlier solve; its nonces are skipped, whatever the number of processes")
+                    .takes_value(true))
+                .arg(
+                    Arg::with_name("save on exit")
+                    .long("save-on-exit")
+                    .help("a file the full solve state is written to when the solve finishes, gives up or runs out of nonces")
+                    .takes_value(true)
+                    .conflicts_with("batch"))
+                .arg(
+                    Arg::with_name("continue")
+                    .long("continue")
+                    .help("a file written by --save-on-exit; its challenge is solved from where it stopped")
                     .takes_value(true)
-                    .default_value("1")))
+                    .conflicts_with_all(&["base string", "base file", "batch", "prefix", "resume"])
+                    .conflicts_with_all(&TARGET_ARG_NAMES))
+                .arg(
+                    Arg::with_name("start")
+                    .long("start")
+                    .help("the first nonce searched; the workers divide the nonces from here on evenly, ex: to resume near the progress an interrupted solve last reported")
+                    .takes_value(true)
+                    .conflicts_with_all(&["resume", "continue", "resume token"]))
+                .arg(
+                    Arg::with_name("count")
+                    .long("count")
+                    .help("how many nonces from --start are searched")
+                    .takes_value(true)
+                    .conflicts_with_all(&["resume", "continue", "resume token"]))
+                .arg(
+                    Arg::with_name("processes")
+                    .long("processes")
+                    .help("solves in this many child processes, each searching its own share of the nonces with --num_processes threads. The children run without attempt limits, deadlines, checkpoints or saved state")
+                    .takes_value(true)
+                    .conflicts_with_all(&["prefix", "verify against device", "auto algorithm", "resume", "continue", "start", "count"])
+                    .conflicts_with_all(&["max attempts", "deadline", "give up if slower than", "checkpoint", "save on exit", "resume token", "print resume token", "compute device"]))
+                .arg(
+                    Arg::with_name("resume token")
+                    .long("resume-token")
+                    .help("a token printed by an earlier solve of the same base; its challenge is solved from where it stopped and an updated token is printed")
+                    .takes_value(true)
+                    .conflicts_with_all(&["batch", "prefix", "resume", "continue"])
+                    .conflicts_with_all(&TARGET_ARG_NAMES))
+                .arg(
+                    Arg::with_name("print resume token")
+                    .long("print-resume-token")
+                    .help("prints a token that --resume-token can continue the solve from when it finishes or gives up"))
+                .arg(algo_arg())
+                .arg(
+                    Arg::with_name("auto algorithm")
+                    .long("auto-algorithm")
+                    .help("detects how the device hashes the base and nonce, using the device (with --verify-against-device) or --sample-nonce"))
+                .arg(
+                    Arg::with_name("sample nonce")
+                    .long("sample-nonce")
+                    .help("a nonce known to solve the challenge, used by --auto-algorithm")
+                    .takes_value(true)
+                    .requires("auto algorithm"))
+                .arg(
+                    Arg::with_name("base digest only")
+                    .long("base-digest-only")
+                    .help("identifies the base in the output by its SHA-256 alone, rather than printing it"))
+                .arg(
+                    Arg::with_name("progress stream")
+                    .long("progress-stream")
+                    .help("where progress is written; the solution is always written to stdout")
+                    .takes_value(true)
+                    .possible_values(&["stdout", "stderr"])
+                    .default_value("stderr"))
+                .arg(
+                    Arg::with_name("big endian")
+                    .long("big-endian")
+                    .help("hashes the nonce big-endian, for locks built to read it that way; sent to the lock the same way with --verify-against"))
+                .arg(
+                    Arg::with_name("quiet")
+                    .short("q")
+                    .long("quiet")
+                    .help("draws no progress at all, for output piped to a file"))
+                .arg(
+                    Arg::with_name("show best")
+                    .long("show-best")
+                    .help("adds the lowest hash seen so far, and its leading zero bits, to the progress"))
+                .arg(
+                    Arg::with_name("otlp endpoint")
+                    .long("otlp-endpoint")
+                    .help("sends solve metrics to this OpenTelemetry collector, ex: http://localhost:4318 (requires the \"telemetry\" feature)")
+                    .takes_value(true))
+                .arg(
+                    Arg::with_name("give up if slower than")
+                    .long("give-up-if-slower-than")
+                    .help("gives up once the measured hashrate projects the solve to take longer than this, ex: 2h")
+                    .takes_value(true))
+                .arg(
+                    Arg::with_name("deadline")
+                    .long("deadline")
+                    .help("stops searching after this long, with or without a solution, ex: 30m")
+                    .takes_value(true))
+                .arg(
+                    Arg::with_name("compute device")
+                    .long("device")
+                    .help("what searches for the nonce, cpu unless given; gpu requires the \"opencl\" feature and falls back to the cpu when unavailable. The gpu always searches from nonce 0, without checkpoints, deadlines or saved state")
+                    .takes_value(true)
+                    .possible_values(&["cpu", "gpu"])
+                    .conflicts_with_all(&["verify against device", "auto algorithm", "resume", "continue"])
+                    .conflicts_with_all(&["start", "count", "deadline", "give up if slower than", "checkpoint", "save on exit", "resume token", "print resume token"]))
+                .arg(
+                    Arg::with_name("format")
+                    .long("format")
+                    .help("how each solve is reported")
+                    .takes_value(true)
+                    .possible_values(&["text", "csv", "json"])
+                    .default_value("text"))
+                .arg(
+                    Arg::with_name("json")
+                    .short("j")
+                    .long("json")
+                    .help("reports each solve as a JSON object; the same as --format json"))
+                .arg(
+                    Arg::with_name("hostname")
+                    .long("hostname")
+                    .help("the device for --verify-against-device and --target-from-device")
+                    .takes_value(true))
+                .arg(
+                    Arg::with_name("port")
+                    .long("port")
+                    .help("the port of the device for --verify-against-device and --target-from-device")
+                    .takes_value(true)))
+        .subcommand(
+            SubCommand::with_name("hash")
+                .about("prints the sha256 hash of a file")
+                .arg(
+                    Arg::with_name("file")
+                        .short("f")
+                        .long("file")
+                        .takes_value(true)
+                        .required(true)))
         .subcommand(
             SubCommand::with_name("make_target")
                 .about("generates a target hash given an amount of time to solve it and a hash rate")
+                .args(&target_args())
+                .arg(
+                    Arg::with_name("estimate")
+                    .long("estimate")
+                    .help("measures this machine's hashrate and prints how long solving the target is expected to take"))
+                .arg(
+                    Arg::with_name("number of processes")
+                    .short("p")
+                    .long("num_processes")
+                    .help("the number of worker processes to measure the hashrate with, or a fraction of the cores, ex: 50% or 0.5x, or auto for one per core")
+                    .takes_value(true)
+                    .default_value("1"))
+                .arg(
+                    Arg::with_name("auto hashrate")
+                    .long("auto-hashrate")
+                    .help("measures this machine's hashrate and uses it in place of --hashrate with --duration")
+                    .requires("duration")
+                    .conflicts_with_all(&["hashrate", "confidence"]))
                 .arg(
-                    Arg::with_name("duration")
-                        .short("d")
-                        .long("duration")
-                        .help("a plain text description of how long it should take to solve, ex: 4hr 25min")
+                    Arg::with_name("format")
+                    .long("format")
+                    .help("how the target is written; bytes writes the raw 32 bytes, for redirecting to a file")
+                    .takes_value(true)
+                    .possible_values(&["hex", "base64", "bytes"])
+                    .default_value("hex")))
+        .subcommand(
+            SubCommand::with_name("estimate-cost")
+                .about("estimates the time, energy and electricity cost of solving a target")
+                .args(&target_args())
+                .arg(
+                    Arg::with_name("watts")
+                        .long("watts")
+                        .help("the power drawn while solving, in watts")
                         .takes_value(true)
                         .required(true))
                 .arg(
-                    Arg::with_name("hashrate")
-                        .short("r")
-                        .long("hashrate")
-                        .help("the hashrate in hashes per second")
+                    Arg::with_name("price")
+                        .long("price")
+                        .help("the price of electricity per kWh")
                         .takes_value(true)
                         .required(true)))
+        .subcommand(
+            SubCommand::with_name("verify")
+                .about("checks nonces against their challenges without solving again")
+                .arg(
+                    Arg::with_name("base string")
+                        .short("b")
+                        .long("base")
+                        .takes_value(true))
+                .args(&target_args())
+                .arg(
+                    Arg::with_name("nonce")
+                        .short("n")
+                        .long("nonce")
+                        .takes_value(true))
+                .arg(
+                    Arg::with_name("batch")
+                        .long("batch")
+                        .help("a file of base, nonce and target entries as JSON lines, or CSV with a base,nonce,target header")
+                        .takes_value(true)
+                        .conflicts_with_all(&["base string", "nonce"]))
+                .arg(algo_arg()))
+        .subcommand(
+            SubCommand::with_name("probe")
+                .about("estimates how many solutions a nonce range holds by hashing a random sample of it")
+                .arg(
+                    Arg::with_name("base string")
+                        .short("b")
+                        .long("base")
+                        .takes_value(true)
+                        .required(true))
+                .args(&target_args())
+                .arg(
+                    Arg::with_name("sample")
+                        .long("sample")
+                        .help("the number of random nonces to hash")
+                        .takes_value(true)
+                        .default_value("100000"))
+                .arg(
+                    Arg::with_name("start")
+                        .long("start")
+                        .help("the first nonce of the range")
+                        .takes_value(true)
+                        .default_value("0"))
+                .arg(
+                    Arg::with_name("end")
+                        .long("end")
+                        .help("the nonce after the last one in the range")
+                        .takes_value(true)
+                        .default_value("18446744073709551615")))
+        .subcommand(
+            SubCommand::with_name("benchmark")
+                .about("compares how quickly contiguous and interleaved workers find a solution among low nonces")
+                .arg(
+                    Arg::with_name("base string")
+                        .short("b")
+                        .long("base")
+                        .takes_value(true)
+                        .required(true))
+                .args(&target_args())
+                .arg(
+                    Arg::with_name("number of processes")
+                        .short("p")
+                        .long("num_processes")
+                        .help("the number of worker processes to generate, or a fraction of the cores, ex: 50% or 0.5x, or auto for one per core")
+                        .takes_value(true)
+                        .default_value("2"))
+                .arg(
+                    Arg::with_name("partition")
+                        .long("partition")
+                        .help("the partition scheme to time; with both, the speedup is reported")
+                        .takes_value(true)
+                        .possible_values(&["contiguous", "interleaved", "both"])
+                        .default_value("both"))
+                .arg(
+                    Arg::with_name("cluster below")
+                        .long("cluster-below")
+                        .help("only solutions below this nonce are accepted; defaults to 16 times the expected attempts")
+                        .takes_value(true)))
+        .subcommand(
+            SubCommand::with_name("match-difficulty")
+                .about("converts a target to another hash algorithm, keeping the expected number of attempts")
+                .arg(
+                    Arg::with_name("from")
+                        .long("from")
+                        .help("the target to match, in hex")
+                        .takes_value(true)
+                        .required(true))
+                .arg(
+                    Arg::with_name("from algorithm")
+                        .long("from-algorithm")
+                        .takes_value(true)
+                        .default_value("sha256"))
+                .arg(
+                    Arg::with_name("to algorithm")
+                        .long("to-algorithm")
+                        .help("ex: sha256, sha256d or sha512")
+                        .takes_value(true)
+                        .required(true)))
+        .subcommand(
+            SubCommand::with_name("encode-nonce")
+                .about("prints a nonce as the bytes a device expects")
+                .arg(
+                    Arg::with_name("nonce")
+                        .short("n")
+                        .long("nonce")
+                        .help("the nonce, as printed by solve")
+                        .takes_value(true)
+                        .required(true))
+                .arg(
+                    Arg::with_name("encoding")
+                        .long("encoding")
+                        .help("byte order and width; solving hashes the nonce as le8")
+                        .takes_value(true)
+                        .possible_values(&["le8", "be8", "le4", "be4"])
+                        .default_value("le8")))
+        .subcommand(
+            SubCommand::with_name("leading-zeros")
+                .about("prints how many leading zero bits a hash has")
+                .arg(
+                    Arg::with_name("hash")
+                        .short("t")
+                        .long("hash")
+                        .help("the hash as 64 hex characters")
+                        .takes_value(true)
+                        .required(true)))
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("solves challenges posted over HTTP (requires the \"serve\" feature)")
+                .arg(
+                    Arg::with_name("address")
+                        .long("address")
+                        .help("the address to listen on")
+                        .takes_value(true)
+                        .default_value("127.0.0.1:8080"))
+                .arg(
+                    Arg::with_name("max concurrent solves")
+                        .long("max-concurrent-solves")
+                        .help("requests beyond this many simultaneous solves are refused")
+                        .takes_value(true)
+                        .default_value("1")))
+        .subcommand(
+            SubCommand::with_name("bench")
+                .about("measures the hashrate with 1, 2, 4, ... workers to find how many to solve with")
+                .arg(
+                    Arg::with_name("max")
+                    .long("max")
+                    .help("the most workers to measure with, at most the number of cores")
+                    .takes_value(true))
+                .arg(
+                    Arg::with_name("length")
+                    .short("l")
+                    .long("length")
+                    .help("the length of time to measure each worker count for, in seconds")
+                    .takes_value(true)
+                    .default_value("5")))
         .subcommand(
             SubCommand::with_name("hashrate_test")
                 .about("runs a short test to estimate the hashrate you can expect from this machine")
@@ -66,9 +628,31 @@ fn main() {
                     Arg::with_name("number of processes")
                     .short("p")
                     .long("num_processes")
-                    .help("the number of worker processes to generate")
+                    .help("the number of worker processes to generate, or a fraction of the cores, ex: 50% or 0.5x, or auto for one per core")
                     .takes_value(true)
-                    .default_value("1")))
+                    .default_value("1"))
+                .arg(
+                    Arg::with_name("base")
+                    .short("b")
+                    .long("base")
+                    .help("tests with this base, since the hashrate depends on the base's length")
+                    .takes_value(true)
+                    .conflicts_with("base length"))
+                .arg(
+                    Arg::with_name("base length")
+                    .long("base-length")
+                    .help("tests with a base of this many bytes")
+                    .takes_value(true))
+                .arg(
+                    Arg::with_name("pin cores")
+                    .long("pin-cores")
+                    .help("keeps each worker thread on its own core (requires the \"affinity\" feature)"))
+                .arg(
+                    Arg::with_name("warmup")
+                    .long("warmup")
+                    .help("seconds of hashing to discard before measuring, so the CPU reaches its steady clock speed")
+                    .takes_value(true)
+                    .default_value("0")))
             .subcommand(SubCommand::with_name("device")
                 .about("interacts with a POW lock over the network")
                 .setting(AppSettings::SubcommandRequiredElseHelp)
@@ -82,12 +666,53 @@ fn main() {
                     .long("port")
                     .takes_value(true)
                     .required(true))
+                .arg(Arg::with_name("trace protocol")
+                    .long("trace-protocol")
+                    .help("writes every byte sent to and received from the device to stderr, in hex"))
+                .arg(Arg::with_name("target binary")
+                    .long("target-binary")
+                    .help("reads the target as 32 raw bytes, for firmware that doesn't send it as hex"))
                 .subcommand(
                     SubCommand::with_name("open")
-                        .about("opens an unlocked lock"))
+                        .about("opens an unlocked lock")
+                        .arg(Arg::with_name("nonce")
+                            .short("n")
+                            .long("nonce")
+                            .help("unlocks the lock with this nonce first if it's still locked")
+                            .takes_value(true))
+                        .arg(Arg::with_name("big endian")
+                            .long("big-endian")
+                            .help("sends the nonce big-endian, for locks built to read it that way")
+                            .requires("nonce")))
                 .subcommand(
                     SubCommand::with_name("status")
                         .about("gets the status (unlocked or locked) of a device"))
+                .subcommand(
+                    SubCommand::with_name("ping")
+                        .about("measures the round trip time of status requests to a device")
+                        .arg(Arg::with_name("count")
+                            .short("c")
+                            .long("count")
+                            .takes_value(true)
+                            .default_value("4")))
+                .subcommand(
+                    SubCommand::with_name("wait")
+                        .about("waits until a device is locked or unlocked")
+                        .arg(Arg::with_name("state")
+                            .long("state")
+                            .takes_value(true)
+                            .possible_values(&["locked", "unlocked"])
+                            .required(true))
+                        .arg(Arg::with_name("timeout")
+                            .long("timeout")
+                            .help("how long to wait before giving up, ex: 60s")
+                            .takes_value(true)
+                            .default_value("60s"))
+                        .arg(Arg::with_name("interval")
+                            .long("interval")
+                            .help("how often to check the status, ex: 500ms")
+                            .takes_value(true)
+                            .default_value("1s")))
                 .subcommand(
                     SubCommand::with_name("base")
                         .about("gets the base string of a lock that is locked"))
@@ -97,11 +722,15 @@ fn main() {
                 .subcommand(
                     SubCommand::with_name("lock")
                         .about("locks a device and sets the target hash")
-                        .arg(Arg::with_name("target")
-                            .short("t")
-                            .long("target")
+                        .args(&target_args())
+                        .arg(Arg::with_name("min expected time")
+                            .long("min-expected-time")
+                            .help("refuses to lock if the target is expected to be solved faster than this at --hashrate, ex: 10min")
                             .takes_value(true)
-                            .required(true)))
+                            .requires("hashrate"))
+                        .arg(Arg::with_name("force")
+                            .long("force")
+                            .help("locks even if the target is easier than --min-expected-time")))
                 .subcommand(
                     SubCommand::with_name("unlock")
                         .about("attempts to unlock a device given a u64 integer nonce")
@@ -109,51 +738,455 @@ fn main() {
                             .short("n")
                             .long("nonce")
                             .takes_value(true)
-                            .required(true)))
+                            .required(true))
+                        .arg(Arg::with_name("big endian")
+                            .long("big-endian")
+                            .help("sends the nonce big-endian, for locks built to read it that way")))
+                .subcommand(
+                    SubCommand::with_name("solve-and-unlock")
+                        .about("fetches the base and target of a locked device, solves it and unlocks it")
+                        .arg(Arg::with_name("number of processes")
+                            .short("p")
+                            .long("num_processes")
+                            .help("the number of worker processes to generate, or a fraction of the cores, ex: 50% or 0.5x, or auto for one per core")
+                            .takes_value(true)
+                            .default_value("1"))
+                        .arg(Arg::with_name("big endian")
+                            .long("big-endian")
+                            .help("hashes and sends the nonce big-endian, for locks built to read it that way")))
             )
         .get_matches();
 
+    matches
+        .value_of("color")
+        .unwrap_or("auto")
+        .parse::<ColorChoice>()
+        .expect("Invalid color choice")
+        .apply();
     match matches.subcommand() {
         ("solve", Some(solve_matches)) => {
-            let base_string = solve_matches
-                .value_of("base string")
-                .expect("Expected a base string");
-            let target_hash =
-                value_t!(solve_matches, "target hash", Sha256Hash).expect("Invalid 256 bit hex");
-            let num_workers = value_t!(solve_matches, "number of processes", u8)
-                .expect("Invalid number of worker processes");
-            cli::solve(base_string.to_string(), target_hash, num_workers);
+            let continue_from = match (
+                solve_matches.value_of("continue"),
+                solve_matches.value_of("resume token"),
+            ) {
+                (Some(path), _) => {
+                    Some(SolveState::load(Path::new(path)).expect("Unable to continue solve"))
+                }
+                (None, Some(token)) => Some(
+                    SolveState::from_token(token, solve_base(solve_matches))
+                        .expect("Unable to continue solve"),
+                ),
+                (None, None) => None,
+            };
+            let challenges = match (solve_matches.value_of("batch"), &continue_from) {
+                // challenges are read from stdin as they arrive instead
+                _ if solve_matches.is_present("stdin jsonl") => vec![],
+                (Some(path), _) => cli::read_batch(path).expect("Invalid batch file"),
+                (None, Some(state)) => vec![(
+                    state.base.clone(),
+                    state.goal().expect("Invalid goal in solve state"),
+                )],
+                _ if solve_matches.is_present("target from device") => {
+                    let host = value_t!(solve_matches, "hostname", String).expect("Invalid host");
+                    let port = value_t!(solve_matches, "port", String).expect("Invalid port");
+                    let mut server = match cli::connect(host, port) {
+                        Some(server) => server,
+                        None => return,
+                    };
+                    vec![cli::challenge_from_device(&mut server)
+                        .expect("Unable to fetch the challenge from the device")]
+                }
+                (None, None) => {
+                    let base = solve_base(solve_matches);
+                    let goal = match solve_matches.value_of("prefix") {
+                        Some(prefix) => {
+                            SolveGoal::prefix_from_hex(prefix).expect("Invalid prefix hex")
+                        }
+                        None => SolveGoal::Target(
+                            target_spec(solve_matches)
+                                .resolve()
+                                .expect("Invalid target"),
+                        ),
+                    };
+                    vec![(base, goal)]
+                }
+            };
+            let challenges = match solve_matches.is_present("min leading zeros") {
+                true => {
+                    let bits = value_t!(solve_matches, "min leading zeros", u32)
+                        .expect("Invalid number of leading zero bits");
+                    challenges
+                        .into_iter()
+                        .map(|(base, goal)| match goal {
+                            SolveGoal::Target(t) => (
+                                base,
+                                SolveGoal::Target(
+                                    target::with_min_leading_zeros(t, bits)
+                                        .expect("Invalid number of leading zero bits"),
+                                ),
+                            ),
+                            // prefixes conflict with --min-leading-zeros
+                            goal => (base, goal),
+                        })
+                        .collect()
+                }
+                false => challenges,
+            };
+            let num_workers = num_workers(solve_matches);
+            let runs = value_t!(solve_matches, "runs", u32).expect("Invalid number of runs");
+            let thread_stack_kb = value_t!(solve_matches, "thread stack size", usize)
+                .expect("Invalid thread stack size");
+            let options = cli::SolveOptions {
+                format: match solve_matches.value_of("format") {
+                    _ if solve_matches.is_present("json") => cli::OutputFormat::Json,
+                    Some("csv") => cli::OutputFormat::Csv,
+                    Some("json") => cli::OutputFormat::Json,
+                    _ => cli::OutputFormat::Text,
+                },
+                runs: runs,
+                salt_each_run: solve_matches.is_present("salt each run"),
+                notify: solve_matches.is_present("notify"),
+                worker_stack_size: thread_stack_kb * 1024,
+                max_attempts: match solve_matches.is_present("max attempts") {
+                    true => Some(
+                        value_t!(solve_matches, "max attempts", u64)
+                            .expect("Invalid maximum number of attempts"),
+                    ),
+                    false => None,
+                },
+                checkpoint_path: solve_matches.value_of("checkpoint").map(PathBuf::from),
+                resume_from: match &continue_from {
+                    Some(state) => Some(state.covered.clone()),
+                    // searching only the range is resuming a solve that searched everything else
+                    None if solve_matches.is_present("start")
+                        || solve_matches.is_present("count") =>
+                    {
+                        let start = match solve_matches.is_present("start") {
+                            true => {
+                                value_t!(solve_matches, "start", u64).expect("Invalid start nonce")
+                            }
+                            false => 0,
+                        };
+                        let end = match solve_matches.is_present("count") {
+                            true => start.saturating_add(
+                                value_t!(solve_matches, "count", u64).expect("Invalid nonce count"),
+                            ),
+                            false => std::u64::MAX,
+                        };
+                        Some(NonceRanges::outside(start, end))
+                    }
+                    None => solve_matches.value_of("resume").map(|path| {
+                        std::fs::read_to_string(path)
+                            .expect("Unable to read checkpoint file")
+                            .parse::<NonceRanges>()
+                            .expect("Invalid checkpoint file")
+                    }),
+                },
+                save_on_exit: solve_matches.value_of("save on exit").map(PathBuf::from),
+                print_resume_token: solve_matches.is_present("print resume token")
+                    || solve_matches.is_present("resume token"),
+                continue_from: continue_from,
+                auto_algorithm: solve_matches.is_present("auto algorithm"),
+                base_digest_only: solve_matches.is_present("base digest only"),
+                progress_stream: match solve_matches.value_of("progress stream") {
+                    _ if solve_matches.is_present("quiet") => ProgressStream::Hidden,
+                    Some("stdout") => ProgressStream::Stdout,
+                    _ => ProgressStream::Stderr,
+                },
+                show_best: solve_matches.is_present("show best"),
+                otlp_endpoint: solve_matches.value_of("otlp endpoint").map(String::from),
+                nonce_byte_order: byte_order(solve_matches),
+                algorithm: algorithm(solve_matches),
+                interrupt: None,
+                pin_cores: solve_matches.is_present("pin cores"),
+                processes: match solve_matches.is_present("processes") {
+                    true => Some(
+                        value_t!(solve_matches, "processes", u8)
+                            .expect("Invalid number of processes"),
+                    )
+                    .filter(|&processes| processes > 0),
+                    false => None,
+                },
+                give_up_if_slower_than: solve_matches.value_of("give up if slower than").map(
+                    |limit| {
+                        limit
+                            .parse::<humantime::Duration>()
+                            .expect("Invalid duration")
+                            .into()
+                    },
+                ),
+                deadline: solve_matches.value_of("deadline").map(|deadline| {
+                    deadline
+                        .parse::<humantime::Duration>()
+                        .expect("Invalid duration")
+                        .into()
+                }),
+                // no default value: clap would apply the gpu's conflicts to it
+                device: match solve_matches.value_of("compute device") {
+                    Some("gpu") => cli::SolveDevice::Gpu,
+                    _ => cli::SolveDevice::Cpu,
+                },
+                sample_nonce: match solve_matches.is_present("sample nonce") {
+                    true => Some(
+                        value_t!(solve_matches, "sample nonce", u64).expect("Invalid sample nonce"),
+                    ),
+                    false => None,
+                },
+                verify_against: match solve_matches.is_present("verify against device") {
+                    true => Some((
+                        value_t!(solve_matches, "hostname", String).expect("Invalid host"),
+                        value_t!(solve_matches, "port", String).expect("Invalid port"),
+                    )),
+                    false => None,
+                },
+            };
+            match solve_matches.is_present("stdin jsonl") {
+                true => cli::solve_jsonl(io::stdin().lock(), io::stdout(), num_workers, &options)
+                    .expect("Unable to solve JSONL challenges"),
+                false => cli::solve(challenges, num_workers, options),
+            }
+        }
+        ("hash", Some(hash_matches)) => {
+            let path = hash_matches.value_of("file").expect("Expected a file");
+            cli::hash_file(path.to_string());
         }
         ("make_target", Some(make_target_matches)) => {
-            let duration_string = make_target_matches
-                .value_of("duration")
-                .expect("Expected a valid duration string");
-            let hash_rate = value_t!(make_target_matches, "hashrate", u64)
-                .expect("Expected a valid integer hashrate");
-            cli::make_target(duration_string.to_string(), hash_rate);
+            let target = match make_target_matches.is_present("auto hashrate") {
+                true => cli::auto_hashrate_target(
+                    make_target_matches
+                        .value_of("duration")
+                        .expect("Expected a duration"),
+                    num_workers(make_target_matches),
+                ),
+                false => target_spec(make_target_matches).resolve(),
+            }
+            .expect("Invalid target");
+            let estimate_workers = match make_target_matches.is_present("estimate") {
+                true => Some(num_workers(make_target_matches)),
+                false => None,
+            };
+            let format = match make_target_matches.value_of("format") {
+                Some("base64") => cli::TargetFormat::Base64,
+                Some("bytes") => cli::TargetFormat::Bytes,
+                _ => cli::TargetFormat::Hex,
+            };
+            let within = make_target_matches.value_of("duration").map(|duration| {
+                duration
+                    .parse::<humantime::Duration>()
+                    .expect("Invalid duration")
+                    .into()
+            });
+            cli::make_target(target, format, estimate_workers, within);
+        }
+        ("estimate-cost", Some(cost_matches)) => {
+            let target = target_spec(cost_matches).resolve().expect("Invalid target");
+            cli::estimate_cost(
+                target,
+                value_t!(cost_matches, "hashrate", u64).expect("Expected a valid integer hashrate"),
+                value_t!(cost_matches, "watts", f64).expect("Invalid watts"),
+                value_t!(cost_matches, "price", f64).expect("Invalid price"),
+            );
+        }
+        ("verify", Some(verify_matches)) => {
+            let valid = match verify_matches.value_of("batch") {
+                Some(path) => {
+                    let contents =
+                        std::fs::read_to_string(path).expect("Unable to read the batch file");
+                    cli::verify_batch(&contents, algorithm(verify_matches), io::stdout())
+                        .expect("Invalid batch file")
+                }
+                None => cli::verify(
+                    verify_matches
+                        .value_of("base string")
+                        .expect("Expected a base string or a batch file")
+                        .as_bytes()
+                        .to_vec(),
+                    target_spec(verify_matches)
+                        .resolve()
+                        .expect("Invalid target"),
+                    value_t!(verify_matches, "nonce", u64).expect("Expected a valid nonce"),
+                    algorithm(verify_matches),
+                ),
+            };
+            if !valid {
+                std::process::exit(1);
+            }
+        }
+        ("probe", Some(probe_matches)) => {
+            let base = probe_matches
+                .value_of("base string")
+                .expect("Expected a base string")
+                .as_bytes()
+                .to_vec();
+            let target = target_spec(probe_matches)
+                .resolve()
+                .expect("Invalid target");
+            let samples = value_t!(probe_matches, "sample", u64).expect("Invalid sample size");
+            let start = value_t!(probe_matches, "start", u64).expect("Invalid start nonce");
+            let end = value_t!(probe_matches, "end", u64).expect("Invalid end nonce");
+            if start >= end {
+                error!("The range must contain at least one nonce");
+                return;
+            }
+            cli::probe(base, SolveGoal::Target(target), start, end, samples);
+        }
+        ("benchmark", Some(benchmark_matches)) => {
+            let base = benchmark_matches
+                .value_of("base string")
+                .expect("Expected a base string")
+                .as_bytes()
+                .to_vec();
+            let target = target_spec(benchmark_matches)
+                .resolve()
+                .expect("Invalid target");
+            let cluster_below = match benchmark_matches.is_present("cluster below") {
+                true => value_t!(benchmark_matches, "cluster below", u64).expect("Invalid nonce"),
+                false => target.expected_attempts_to_solve().saturating_mul(16),
+            };
+            let partitions = match benchmark_matches.value_of("partition") {
+                Some("contiguous") => vec![PartitionScheme::Contiguous],
+                Some("interleaved") => vec![PartitionScheme::Interleaved],
+                _ => vec![PartitionScheme::Contiguous, PartitionScheme::Interleaved],
+            };
+            cli::benchmark(
+                base,
+                SolveGoal::Target(target),
+                num_workers(benchmark_matches),
+                partitions,
+                cluster_below,
+            );
+        }
+        ("match-difficulty", Some(match_matches)) => {
+            cli::match_difficulty(
+                value_t!(match_matches, "from", String).expect("Invalid target"),
+                value_t!(match_matches, "from algorithm", String).expect("Invalid algorithm"),
+                value_t!(match_matches, "to algorithm", String).expect("Invalid algorithm"),
+            );
+        }
+        ("encode-nonce", Some(encode_matches)) => {
+            cli::encode_nonce(
+                value_t!(encode_matches, "nonce", u64).expect("Invalid nonce"),
+                value_t!(encode_matches, "encoding", NonceEncoding).expect("Invalid encoding"),
+            );
+        }
+        ("leading-zeros", Some(leading_zeros_matches)) => {
+            let hash = leading_zeros_matches
+                .value_of("hash")
+                .expect("Expected a hash")
+                .parse::<Sha256Hash>()
+                .expect("Invalid hash");
+            cli::leading_zeros(hash);
+        }
+        ("serve", Some(serve_matches)) => {
+            let address = value_t!(serve_matches, "address", String).expect("Invalid address");
+            let max_concurrent_solves = value_t!(serve_matches, "max concurrent solves", usize)
+                .expect("Invalid maximum number of concurrent solves");
+            cli::serve(address, max_concurrent_solves);
+        }
+        ("bench", Some(bench_matches)) => {
+            let cores = cli::auto_worker_count(
+                std::thread::available_parallelism()
+                    .map(|n| n.get())
+                    .unwrap_or(1),
+            );
+            let max_workers = match bench_matches.value_of("max") {
+                Some(_) => value_t!(bench_matches, "max", u8).expect("Invalid maximum workers"),
+                None => cores,
+            };
+            let length = value_t!(bench_matches, "length", u64).expect("Invalid length");
+            cli::bench(max_workers.min(cores), length);
         }
         ("hashrate_test", Some(hashrate_test_matches)) => {
-            let num_workers = value_t!(hashrate_test_matches, "number of processes", u8)
-                .expect("Invalid number of worker processes");
+            let num_workers = num_workers(hashrate_test_matches);
             let length =
                 value_t!(hashrate_test_matches, "length", u64).expect("Invalid test time length");
-            cli::hashrate_test(num_workers, length);
+            let base = match hashrate_test_matches.value_of("base") {
+                Some(base) => Some(base.as_bytes().to_vec()),
+                None if hashrate_test_matches.is_present("base length") => {
+                    let base_length = value_t!(hashrate_test_matches, "base length", usize)
+                        .expect("Invalid base length");
+                    Some(vec![b'a'; base_length])
+                }
+                None => None,
+            };
+            let warmup = value_t!(hashrate_test_matches, "warmup", u64).expect("Invalid warmup");
+            cli::hashrate_test(
+                num_workers,
+                length,
+                base,
+                Duration::from_secs(warmup),
+                hashrate_test_matches.is_present("pin cores"),
+            );
         }
         ("device", Some(device_matches)) => {
             let host = value_t!(device_matches, "hostname", String).expect("Invalid host");
             let port = value_t!(device_matches, "port", String).expect("Invalid port");
-            let server = PowServer::new(host, port);
+            let mut server = match cli::connect(host, port) {
+                Some(server) => server,
+                None => return,
+            };
+            if device_matches.is_present("trace protocol") {
+                server.set_trace(Box::new(std::io::stderr()));
+            }
+            server.set_target_binary(device_matches.is_present("target binary"));
             match device_matches.subcommand() {
                 ("status", _) => cli::get_status(server),
+                ("ping", Some(ping_matches)) => {
+                    let count = value_t!(ping_matches, "count", u32).expect("Invalid count");
+                    cli::ping(server, count);
+                }
                 ("unlock", Some(unlock_matches)) => {
                     let nonce = value_t!(unlock_matches, "nonce", u64).expect("Invalid nonce");
+                    server.set_nonce_byte_order(byte_order(unlock_matches));
                     cli::unlock(server, nonce);
                 }
+                ("solve-and-unlock", Some(solve_matches)) => {
+                    cli::solve_and_unlock(
+                        server,
+                        num_workers(solve_matches),
+                        byte_order(solve_matches),
+                    );
+                }
+                ("wait", Some(wait_matches)) => {
+                    let duration = |name: &str| -> Duration {
+                        wait_matches
+                            .value_of(name)
+                            .expect("Expected a duration")
+                            .parse::<humantime::Duration>()
+                            .expect("Invalid duration")
+                            .into()
+                    };
+                    cli::wait(
+                        server,
+                        value_t!(wait_matches, "state", LockStatus).expect("Invalid state"),
+                        duration("interval"),
+                        duration("timeout"),
+                    );
+                }
+                ("open", Some(open_matches)) if open_matches.is_present("nonce") => {
+                    let nonce = value_t!(open_matches, "nonce", u64).expect("Invalid nonce");
+                    server.set_nonce_byte_order(byte_order(open_matches));
+                    cli::open_with_nonce(server, nonce);
+                }
                 ("open", _) => cli::open(server),
                 ("base", _) => cli::base(server),
                 ("target", _) => cli::target(server),
                 ("lock", Some(lock_matches)) => {
-                    let target = value_t!(lock_matches, "target", String).expect("Invalid port");
+                    let target = target_spec(lock_matches).resolve().expect("Invalid target");
+                    if let Some(min_time) = lock_matches.value_of("min expected time") {
+                        let min_time: Duration = min_time
+                            .parse::<humantime::Duration>()
+                            .expect("Invalid minimum expected time")
+                            .into();
+                        let hash_rate =
+                            value_t!(lock_matches, "hashrate", u64).expect("Invalid hashrate");
+                        if let Err(e) = cli::check_min_expected_time(&target, min_time, hash_rate) {
+                            if !lock_matches.is_present("force") {
+                                error!("{}", e);
+                                return;
+                            }
+                        }
+                    }
                     cli::lock(server, target);
                 }
                 ("", None) => println!("No subcommand was used, try \"help\""),
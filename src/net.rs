@@ -1,41 +1,287 @@
 use rustc_serialize as serialize;
 
-use self::serialize::hex::FromHex;
-use crate::hash::TNonce;
+pub mod mock;
+
+use self::serialize::hex::ToHex;
+use crate::hash::{HashSolution, NonceByteOrder, Sha256Hash, TNonce};
+use log::warn;
 use std::io::prelude::*;
-use std::io::{BufRead, BufReader};
-use std::net::TcpStream;
+use std::io::{self, BufRead, BufReader};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
+#[derive(Debug)]
 pub enum PowLockError {
     InvalidOperationWhenLocked,
     InvalidOperationWhenUnlocked,
     Unsuccessful,
-    Connection,
+    Connection(io::ErrorKind), // why we couldn't reach the lock, ex: ConnectionRefused
+    Timeout,                   // the lock didn't answer in time, see set_timeout
     Unknown,
 }
 
+impl std::fmt::Display for PowLockError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            PowLockError::InvalidOperationWhenLocked => write!(f, "Lock is locked; cannot open"),
+            PowLockError::InvalidOperationWhenUnlocked => {
+                write!(f, "Lock is unlocked; there is no base or target")
+            }
+            PowLockError::Unsuccessful => {
+                write!(
+                    f,
+                    "Unsuccessful. Hash of base and nonce not less than target."
+                )
+            }
+            PowLockError::Connection(kind) => write!(
+                f,
+                "Error connecting with lock: {}",
+                connection_error_message(*kind)
+            ),
+            PowLockError::Timeout => write!(f, "The lock didn't respond in time"),
+            PowLockError::Unknown => write!(f, "Unknown error"),
+        }
+    }
+}
+
+impl std::error::Error for PowLockError {}
+
+pub fn connection_error_message(kind: io::ErrorKind) -> String {
+    match kind {
+        io::ErrorKind::ConnectionRefused => "connection refused".to_string(),
+        io::ErrorKind::NotFound => "host not found".to_string(),
+        io::ErrorKind::TimedOut => "timed out".to_string(),
+        io::ErrorKind::ConnectionReset => "connection reset".to_string(),
+        io::ErrorKind::UnexpectedEof => "connection closed".to_string(),
+        other => format!("{:?}", other),
+    }
+}
+
+// sending a request failed, by timing out or losing the connection
+fn request_error(e: io::Error) -> PowLockError {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => PowLockError::Timeout,
+        kind => PowLockError::Connection(kind),
+    }
+}
+
+// reading the response failed; a read timeout shows up as WouldBlock on unix
+// and TimedOut on windows
+fn response_error(e: io::Error) -> PowLockError {
+    match e.kind() {
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => PowLockError::Timeout,
+        kind @ io::ErrorKind::UnexpectedEof => PowLockError::Connection(kind),
+        _ => PowLockError::Unknown,
+    }
+}
+
+// a line the lock sent without its line ending. Only the ending is removed,
+// since other whitespace could be part of a base. A lock that sends nothing
+// at all is treated as misbehaving
+fn response_body(response: &str) -> Result<String, PowLockError> {
+    match response.trim_end_matches(&['\r', '\n'][..]) {
+        "" => Err(PowLockError::Unknown),
+        body => Ok(body.to_string()),
+    }
+}
+
+// `addr` and `port` joined for resolving; IPv6 literals, ex: fe80::1, are
+// bracketed so their colons aren't mistaken for the port's
+fn socket_address(addr: &str, port: &str) -> String {
+    match addr.contains(':') && !addr.starts_with('[') {
+        true => format!("[{}]:{}", addr, port),
+        false => format!("{}:{}", addr, port),
+    }
+}
+
+// whether a lock is holding a challenge
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LockStatus {
+    Locked,
+    Unlocked,
+}
+
+impl LockStatus {
+    // the lock answers a status request with 1 when locked and 0 when not
+    fn from_response(response: &str) -> Result<LockStatus, PowLockError> {
+        if response.starts_with("1") {
+            return Ok(LockStatus::Locked);
+        }
+        if response.starts_with("0") {
+            return Ok(LockStatus::Unlocked);
+        }
+        Err(PowLockError::Unknown)
+    }
+}
+
+impl std::fmt::Display for LockStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LockStatus::Locked => write!(f, "Locked"),
+            LockStatus::Unlocked => write!(f, "Unlocked"),
+        }
+    }
+}
+
+impl FromStr for LockStatus {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "locked" => Ok(LockStatus::Locked),
+            "unlocked" => Ok(LockStatus::Unlocked),
+            _ => Err(format!("Unknown lock status {}", s)),
+        }
+    }
+}
+
+// what a locked device asks to be solved
+#[derive(Debug, Clone, PartialEq)]
+pub struct Challenge {
+    pub base: Vec<u8>,
+    pub target: Sha256Hash,
+}
+
+// how long to wait on the lock before giving up on a request
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// how the lock refuses a request, ex: asking for the target when it's unlocked
+const ERROR_RESPONSE: &[u8] = b"ERROR\n";
+
 pub struct PowServer {
     stream: TcpStream,
+    // reads from the same connection as `stream`, kept between requests so
+    // anything the lock sent past one response is there for the next
+    reader: BufReader<TcpStream>,
+    trace: Option<Box<dyn Write + Send>>, // every byte sent and received is logged here in hex
+    target_binary: bool, // the lock sends its target as 32 raw bytes rather than a hex line
+    nonce_byte_order: NonceByteOrder,
 }
 
 impl PowServer {
     pub fn new(addr: String, port: String) -> Self {
+        PowServer::connect_with_retries(addr, port, 1, Duration::from_secs(0))
+            .expect("Failed to connect to server")
+    }
+
+    // like `connect`, but tries up to `attempts` times, waiting `backoff`
+    // between tries, for locks on flaky networks that refuse now and then
+    pub fn connect_with_retries(
+        addr: String,
+        port: String,
+        attempts: u32,
+        backoff: Duration,
+    ) -> Result<Self, PowLockError> {
+        let mut attempt = 1;
+        loop {
+            match PowServer::connect(addr.clone(), port.clone()) {
+                Err(_) if attempt < attempts => {
+                    attempt += 1;
+                    std::thread::sleep(backoff);
+                }
+                result => return result,
+            }
+        }
+    }
+
+    // like `new`, but reports why the connection failed rather than panicking.
+    // The address is resolved first so an unknown host is reported as
+    // NotFound instead of whatever the resolver's error happens to be. Each
+    // address a host resolves to is tried in turn
+    pub fn connect(addr: String, port: String) -> Result<Self, PowLockError> {
+        let addrs: Vec<SocketAddr> = socket_address(&addr, &port)
+            .to_socket_addrs()
+            .map_err(|_| PowLockError::Connection(io::ErrorKind::NotFound))?
+            .collect();
+        if addrs.is_empty() {
+            return Err(PowLockError::Connection(io::ErrorKind::NotFound));
+        }
         let stream =
-            TcpStream::connect(format!("{}:{}", addr, port)).expect("Failed to connect to server");
-        PowServer { stream: stream }
+            TcpStream::connect(&addrs[..]).map_err(|e| PowLockError::Connection(e.kind()))?;
+        let reader = stream
+            .try_clone()
+            .map_err(|e| PowLockError::Connection(e.kind()))?;
+        let mut server = PowServer {
+            stream: stream,
+            reader: BufReader::new(reader),
+            trace: None,
+            target_binary: false,
+            nonce_byte_order: NonceByteOrder::Little,
+        };
+        server.set_timeout(DEFAULT_TIMEOUT);
+        Ok(server)
     }
 
-    pub fn open(&mut self) -> Result<(), PowLockError> {
-        self.stream
-            .write(b"O\n")
-            .map_err(|_| PowLockError::Connection)?;
+    // how long a request waits to be sent and answered before failing
+    // with PowLockError::Timeout
+    pub fn set_timeout(&mut self, timeout: Duration) -> () {
+        // only a zero duration is refused, and that's no timeout at all
+        let timeout = Some(timeout).filter(|t| *t > Duration::from_secs(0));
+        let _ = self.stream.set_read_timeout(timeout);
+        let _ = self.stream.set_write_timeout(timeout);
+    }
+
+    // logs every byte sent to the lock as "> <hex>" and every response as
+    // "< <hex>", for debugging the protocol
+    pub fn set_trace(&mut self, trace: Box<dyn Write + Send>) -> () {
+        self.trace = Some(trace);
+    }
+
+    // for firmware that sends the target as 32 raw bytes instead of hex
+    pub fn set_target_binary(&mut self, target_binary: bool) -> () {
+        self.target_binary = target_binary;
+    }
+
+    // for firmware that reads the unlock nonce as big-endian; the solve
+    // must hash the nonce in the same order
+    pub fn set_nonce_byte_order(&mut self, order: NonceByteOrder) -> () {
+        self.nonce_byte_order = order;
+    }
+
+    fn send(&mut self, message: &[u8]) -> io::Result<usize> {
+        if let Some(trace) = &mut self.trace {
+            // tracing is best effort; it shouldn't fail the request
+            let _ = writeln!(trace, "> {}", message.to_hex());
+        }
+        self.stream.write(message)
+    }
 
-        let mut reader = BufReader::new(&self.stream);
+    // reads a whole line, however many pieces the lock sends it in, ex: over
+    // a slow serial bridge. The lock closing the connection first is an
+    // UnexpectedEof, unless it sent part of a line, which is returned as is
+    fn read_response(&mut self) -> io::Result<String> {
         let mut response = String::new();
+        let result = loop {
+            match self.reader.read_line(&mut response) {
+                Ok(0) if response.is_empty() => {
+                    break Err(io::Error::from(io::ErrorKind::UnexpectedEof))
+                }
+                Ok(0) => break Ok(()),
+                Ok(_) if response.ends_with('\n') => break Ok(()),
+                Ok(_) => continue,
+                Err(e) => break Err(e),
+            }
+        };
+        if let Some(trace) = &mut self.trace {
+            let _ = writeln!(trace, "< {}", response.as_bytes().to_hex());
+        }
+        result.map(|_| response)
+    }
 
-        reader
-            .read_line(&mut response)
-            .map_err(|_| PowLockError::Unknown)?;
+    // reads exactly `len` bytes, for responses that aren't lines
+    fn read_exact_response(&mut self, len: usize) -> io::Result<Vec<u8>> {
+        let mut response = vec![0u8; len];
+        let result = self.reader.read_exact(&mut response);
+        if let Some(trace) = &mut self.trace {
+            let _ = writeln!(trace, "< {}", response.to_hex());
+        }
+        result.map(|_| response)
+    }
+
+    pub fn open(&mut self) -> Result<(), PowLockError> {
+        self.send(b"O\n").map_err(request_error)?;
+
+        let response = self.read_response().map_err(response_error)?;
         if response.starts_with("ERROR") {
             return Err(PowLockError::InvalidOperationWhenLocked);
         }
@@ -47,23 +293,16 @@ impl PowServer {
 
     // attempts to unlock a lock with a u64 nonce
     pub fn unlock(&mut self, nonce: u64) -> Result<(), PowLockError> {
-        let nonce_bytes = nonce.as_hex_bytes();
+        let nonce_bytes = nonce.as_hex_bytes(self.nonce_byte_order);
         let mut message = vec![];
 
         message.extend(b"u");
         message.extend(nonce_bytes.as_bytes());
         message.extend(b"\n");
 
-        self.stream
-            .write(&message)
-            .map_err(|_| PowLockError::Connection)?;
-
-        let mut reader = BufReader::new(&self.stream);
-        let mut response = String::new();
+        self.send(&message).map_err(request_error)?;
 
-        reader
-            .read_line(&mut response)
-            .map_err(|_| PowLockError::Unknown)?;
+        let response = self.read_response().map_err(response_error)?;
 
         if response.starts_with("1") {
             return Ok(());
@@ -74,98 +313,478 @@ impl PowServer {
         Err(PowLockError::Unknown)
     }
 
-    pub fn get_status(&mut self) -> Result<String, PowLockError> {
-        self.stream
-            .write(b"s\n")
-            .map_err(|_| PowLockError::Connection)?;
-        let mut reader = BufReader::new(&self.stream);
-        let mut response = String::new();
+    // unlocks with the nonce of a solution found for this lock's challenge
+    pub fn unlock_with_solution(&mut self, solution: &HashSolution) -> Result<(), PowLockError> {
+        self.unlock(solution.nonce)
+    }
 
-        reader
-            .read_line(&mut response)
-            .map_err(|_| PowLockError::Unknown)?;
-        if response.starts_with("1") {
-            return Ok("Locked".to_string());
-        }
-        if response.starts_with("0") {
-            return Ok("Unlocked".to_string());
+    pub fn get_status(&mut self) -> Result<LockStatus, PowLockError> {
+        self.send(b"s\n").map_err(request_error)?;
+        let response = self.read_response().map_err(response_error)?;
+        LockStatus::from_response(&response)
+    }
+
+    // polls the status every `poll_interval` until it's `desired`, failing
+    // with PowLockError::Timeout once `timeout` has passed
+    pub fn wait_for_status(
+        &mut self,
+        desired: LockStatus,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> Result<(), PowLockError> {
+        let start_time = Instant::now();
+        loop {
+            if self.get_status()? == desired {
+                return Ok(());
+            }
+            if start_time.elapsed() + poll_interval > timeout {
+                return Err(PowLockError::Timeout);
+            }
+            std::thread::sleep(poll_interval);
         }
-        Err(PowLockError::Unknown)
     }
 
     pub fn get_base(&mut self) -> Result<String, PowLockError> {
-        self.stream
-            .write(b"b\n")
-            .map_err(|_| PowLockError::Connection)?;
-        let mut reader = BufReader::new(&self.stream);
-        let mut response = String::new();
-
-        reader
-            .read_line(&mut response)
-            .map_err(|_| PowLockError::Unknown)?;
+        self.send(b"b\n").map_err(request_error)?;
+        let response = self.read_response().map_err(response_error)?;
         if response.starts_with("ERROR") {
             return Err(PowLockError::InvalidOperationWhenUnlocked);
         }
-        Ok(response)
+        response_body(&response)
     }
 
-    pub fn get_target(&mut self) -> Result<String, PowLockError> {
-        self.stream
-            .write(b"t\n")
-            .map_err(|_| PowLockError::Connection)?;
-        let mut reader = BufReader::new(&self.stream);
-        let mut response = String::new();
+    // re-fetches the base and compares it with one fetched earlier, so a
+    // long solve can notice the device rotated its challenge and start over
+    pub fn base_changed_since(&mut self, previous: &str) -> Result<bool, PowLockError> {
+        Ok(self.get_base()?.trim() != previous.trim())
+    }
 
-        reader
-            .read_line(&mut response)
-            .map_err(|_| PowLockError::Unknown)?;
+    pub fn get_target(&mut self) -> Result<String, PowLockError> {
+        if self.target_binary {
+            return self.get_target_hash().map(|target| target.to_string());
+        }
+        self.send(b"t\n").map_err(request_error)?;
+        let response = self.read_response().map_err(response_error)?;
         if response.starts_with("ERROR") {
             return Err(PowLockError::InvalidOperationWhenUnlocked);
         }
-        Ok(response)
+        response_body(&response)
+    }
+
+    // the target parsed, however the lock sends it
+    pub fn get_target_hash(&mut self) -> Result<Sha256Hash, PowLockError> {
+        if !self.target_binary {
+            return self
+                .get_target()?
+                .trim()
+                .parse::<Sha256Hash>()
+                .map_err(|_| PowLockError::Unknown);
+        }
+        self.send(b"t\n").map_err(request_error)?;
+        // an unlocked lock still answers with an error line, so that's read
+        // first; a real target beginning with those bytes is practically impossible
+        let head = self
+            .read_exact_response(ERROR_RESPONSE.len())
+            .map_err(response_error)?;
+        if head == ERROR_RESPONSE {
+            return Err(PowLockError::InvalidOperationWhenUnlocked);
+        }
+        let rest = self
+            .read_exact_response(32 - head.len())
+            .map_err(response_error)?;
+        let mut target = [0u8; 32];
+        target[..head.len()].copy_from_slice(&head);
+        target[head.len()..].copy_from_slice(&rest);
+        Ok(Sha256Hash::from_bytes(target))
+    }
+
+    // fetches the base and target together, parsed and ready to solve
+    pub fn challenge(&mut self) -> Result<Challenge, PowLockError> {
+        let base = self.get_base()?;
+        Ok(Challenge {
+            base: base.into_bytes(),
+            target: self.get_target_hash()?,
+        })
     }
 
     // locks a lock given a target hash
     // returns the base string the lock generated
     pub fn lock(&mut self, target: String) -> Result<String, PowLockError> {
-        if target.len() != 64 {
-            println!("Expected 64 chars for target representing a SHA256 hash in hex");
-            return Err(PowLockError::Unknown);
-        }
-
-        let mut hash: [u8; 32] = [0; 32];
-        match target.from_hex() {
-            Ok(r) => {
-                for (i, &v) in r.iter().enumerate() {
-                    hash[i] = v;
-                }
-            }
-            Err(_) => {
-                println!("Serialization failed for target: {}", target);
+        let hash = match target.parse::<Sha256Hash>() {
+            Ok(hash) => hash,
+            Err(e) => {
+                warn!("Invalid target {}: {}", target, e);
                 return Err(PowLockError::Unknown);
             }
-        }
+        };
 
         let mut message = vec![];
 
         message.extend(b"l");
-        message.extend(hash.iter());
+        message.extend(hash.as_bytes().iter());
         message.extend(b"\n");
 
-        self.stream
-            .write(&message)
-            .map_err(|_| PowLockError::Connection)?;
-
-        let mut reader = BufReader::new(&self.stream);
-        let mut response = String::new();
+        self.send(&message).map_err(request_error)?;
 
-        reader
-            .read_line(&mut response)
-            .map_err(|_| PowLockError::Unknown)?;
+        let response = self.read_response().map_err(response_error)?;
 
         if response.starts_with("ERROR") {
             return Err(PowLockError::InvalidOperationWhenLocked);
         }
-        Ok(response)
+        response_body(&response)
+    }
+
+    // sends `count` status requests and records the round trip time of each;
+    // a failed request is recorded as an error without ending the run
+    pub fn ping(&mut self, count: u32) -> Vec<Result<Duration, PowLockError>> {
+        let mut samples = vec![];
+        for _ in 0..count {
+            let start_time = Instant::now();
+            samples.push(self.get_status().map(|_| start_time.elapsed()));
+        }
+        samples
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::serialize::hex::ToHex;
+    use super::{socket_address, Challenge, LockStatus, PowLockError, PowServer};
+    use crate::hash::{HashWorkerFarm, NonceByteOrder, Sha256Hash, TNonce};
+    use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+    use std::net::TcpListener;
+    use std::str::FromStr;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    // collects a trace so the test can read it back
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // spawns a server that answers every line it receives with `reply`
+    fn spawn_mock_server(reply: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for line in BufReader::new(stream).lines() {
+                if line.is_err() {
+                    return;
+                }
+                writer.write_all(reply.as_bytes()).unwrap();
+            }
+        });
+        port.to_string()
+    }
+
+    // spawns a server that answers each line it receives with the next reply
+    fn spawn_scripted_server<T: AsRef<[u8]> + Send + 'static>(replies: Vec<T>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            for (line, reply) in BufReader::new(stream).lines().zip(replies) {
+                if line.is_err() {
+                    return;
+                }
+                writer.write_all(reply.as_ref()).unwrap();
+            }
+        });
+        port.to_string()
+    }
+
+    #[test]
+    fn it_pings_a_lock() {
+        let port = spawn_mock_server("1\n");
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        let samples = server.ping(3);
+        assert_eq!(samples.len(), 3);
+        assert!(samples.iter().all(|sample| sample.is_ok()));
+    }
+
+    #[test]
+    fn it_reports_a_refused_connection() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        // the listener is dropped, so nothing is listening on the port
+        match PowServer::connect("127.0.0.1".to_string(), port.to_string()) {
+            Err(PowLockError::Connection(kind)) => assert_eq!(kind, ErrorKind::ConnectionRefused),
+            _ => panic!("Expected a connection error"),
+        }
+    }
+
+    #[test]
+    fn it_retries_a_refused_connection() {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let start = Instant::now();
+        let result = PowServer::connect_with_retries(
+            "127.0.0.1".to_string(),
+            port.to_string(),
+            3,
+            Duration::from_millis(100),
+        );
+        match result {
+            Err(PowLockError::Connection(kind)) => assert_eq!(kind, ErrorKind::ConnectionRefused),
+            _ => panic!("Expected a connection error"),
+        }
+        // two waits between the three attempts
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn it_times_out_when_the_lock_never_replies() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            // holds the connection open without ever answering
+            let (_stream, _) = listener.accept().unwrap();
+            std::thread::sleep(Duration::from_secs(10));
+        });
+        let mut server = PowServer::new("127.0.0.1".to_string(), port.to_string());
+        server.set_timeout(Duration::from_millis(200));
+        let start = Instant::now();
+        match server.get_status() {
+            Err(PowLockError::Timeout) => {}
+            _ => panic!("Expected a timeout"),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn it_brackets_ipv6_addresses() {
+        assert_eq!(socket_address("192.168.1.20", "8080"), "192.168.1.20:8080");
+        assert_eq!(socket_address("lock.local", "8080"), "lock.local:8080");
+        assert_eq!(socket_address("fe80::1", "8080"), "[fe80::1]:8080");
+        assert_eq!(socket_address("[fe80::1]", "8080"), "[fe80::1]:8080");
+    }
+
+    #[test]
+    fn it_reports_an_unresolvable_host() {
+        match PowServer::connect("no-such-host.invalid".to_string(), "8080".to_string()) {
+            Err(PowLockError::Connection(kind)) => assert_eq!(kind, ErrorKind::NotFound),
+            _ => panic!("Expected a connection error"),
+        }
+    }
+
+    #[test]
+    fn it_describes_each_error() {
+        let messages = [
+            (
+                PowLockError::InvalidOperationWhenLocked,
+                "Lock is locked; cannot open",
+            ),
+            (
+                PowLockError::InvalidOperationWhenUnlocked,
+                "Lock is unlocked; there is no base or target",
+            ),
+            (
+                PowLockError::Unsuccessful,
+                "Unsuccessful. Hash of base and nonce not less than target.",
+            ),
+            (
+                PowLockError::Connection(ErrorKind::ConnectionRefused),
+                "Error connecting with lock: connection refused",
+            ),
+            (PowLockError::Timeout, "The lock didn't respond in time"),
+            (PowLockError::Unknown, "Unknown error"),
+        ];
+        for (error, message) in messages.iter() {
+            assert_eq!(error.to_string(), *message);
+        }
+        // usable wherever a boxed error is
+        let error: Box<dyn std::error::Error> = Box::new(PowLockError::Timeout);
+        assert_eq!(error.to_string(), "The lock didn't respond in time");
+    }
+
+    #[test]
+    fn it_traces_a_status_request() {
+        let port = spawn_mock_server("1\n");
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        let trace = SharedBuffer::default();
+        server.set_trace(Box::new(trace.clone()));
+        assert_eq!(server.get_status().unwrap(), LockStatus::Locked);
+        let trace = String::from_utf8(trace.0.lock().unwrap().clone()).unwrap();
+        assert_eq!(trace, "> 730a\n< 310a\n");
+    }
+
+    #[test]
+    fn it_reads_the_status_from_the_wire() {
+        assert_eq!(
+            LockStatus::from_response("1\n").unwrap(),
+            LockStatus::Locked
+        );
+        assert_eq!(
+            LockStatus::from_response("0\n").unwrap(),
+            LockStatus::Unlocked
+        );
+        assert!(matches!(
+            LockStatus::from_response("ERROR\n"),
+            Err(PowLockError::Unknown)
+        ));
+        assert_eq!("unlocked".parse(), Ok(LockStatus::Unlocked));
+        assert_eq!(LockStatus::Locked.to_string(), "Locked");
+    }
+
+    #[test]
+    fn it_waits_for_the_lock_to_unlock() {
+        let port = spawn_scripted_server(vec!["1\n", "1\n", "1\n", "0\n"]);
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        let poll = Duration::from_millis(10);
+        assert!(server
+            .wait_for_status(LockStatus::Unlocked, poll, Duration::from_secs(5))
+            .is_ok());
+
+        let port = spawn_mock_server("1\n");
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        match server.wait_for_status(LockStatus::Unlocked, poll, Duration::from_millis(200)) {
+            Err(PowLockError::Timeout) => {}
+            _ => panic!("Expected a timeout"),
+        }
+    }
+
+    #[test]
+    fn it_notices_the_base_changed() {
+        let port = spawn_scripted_server(vec!["first\n", "first\n", "second\n"]);
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        let base = server.get_base().unwrap();
+        assert!(!server.base_changed_since(&base).unwrap());
+        assert!(server.base_changed_since(&base).unwrap());
+    }
+
+    #[test]
+    fn it_fetches_the_challenge() {
+        let target = "00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        let port = spawn_scripted_server(vec![
+            "abase\n",
+            "00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff\n",
+        ]);
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        assert_eq!(
+            server.challenge().unwrap(),
+            Challenge {
+                base: b"abase".to_vec(),
+                target: Sha256Hash::from_str(target).unwrap(),
+            }
+        );
+    }
+
+    #[test]
+    fn it_returns_the_base_without_its_newline() {
+        let target = "1111111111111111111111111111111111111111111111111111111111111111";
+        let port = spawn_scripted_server(vec!["somebase\n", "somebase\r\n", "\n"]);
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        assert_eq!(server.lock(target.to_string()).unwrap(), "somebase");
+        assert_eq!(server.get_base().unwrap(), "somebase");
+        match server.get_base() {
+            Err(PowLockError::Unknown) => {}
+            _ => panic!("Expected an empty base to be refused"),
+        }
+    }
+
+    #[test]
+    fn it_unlocks_with_a_solution_to_the_challenge() {
+        let port = spawn_scripted_server(vec![
+            "abase\n",
+            "00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff\n",
+            "1\n",
+        ]);
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        let trace = SharedBuffer::default();
+        server.set_trace(Box::new(trace.clone()));
+        let challenge = server.challenge().unwrap();
+        let farm = HashWorkerFarm::new(challenge.base, challenge.target, 1);
+        let solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        server.unlock_with_solution(&solution).unwrap();
+        let trace = String::from_utf8(trace.0.lock().unwrap().clone()).unwrap();
+        let mut unlock = b"u".to_vec();
+        unlock.extend(
+            solution
+                .nonce
+                .as_hex_bytes(NonceByteOrder::Little)
+                .as_bytes(),
+        );
+        unlock.extend(b"\n");
+        assert!(trace.contains(&format!("> {}\n< 310a\n", unlock.to_hex())));
+    }
+
+    #[test]
+    fn it_keeps_a_second_line_sent_with_the_first() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            lines.next();
+            // answers the status request and the base request to come at once
+            writer.write_all(b"1\nsomebase\n").unwrap();
+            for _ in lines {}
+        });
+        let mut server = PowServer::new("127.0.0.1".to_string(), port.to_string());
+        server.set_timeout(Duration::from_secs(1));
+        assert_eq!(server.get_status().unwrap(), LockStatus::Locked);
+        assert_eq!(server.get_base().unwrap(), "somebase");
+    }
+
+    #[test]
+    fn it_reads_a_status_sent_in_pieces() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut writer = stream.try_clone().unwrap();
+            let mut lines = BufReader::new(stream).lines();
+            lines.next();
+            writer.write_all(b"1").unwrap();
+            writer.flush().unwrap();
+            std::thread::sleep(Duration::from_millis(50));
+            writer.write_all(b"\n").unwrap();
+            for _ in lines {}
+        });
+        let mut server = PowServer::new("127.0.0.1".to_string(), port.to_string());
+        server.set_timeout(Duration::from_secs(1));
+        assert_eq!(server.get_status().unwrap(), LockStatus::Locked);
+    }
+
+    #[test]
+    fn it_reports_the_lock_closing_the_connection() {
+        let port = spawn_scripted_server(Vec::<&[u8]>::new());
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        assert!(matches!(
+            server.get_status(),
+            Err(PowLockError::Connection(ErrorKind::UnexpectedEof))
+        ));
+    }
+
+    #[test]
+    fn it_reads_a_binary_target() {
+        let mut target = vec![0x00, 0x00, 0x0f];
+        target.resize(32, 0xff);
+        let port = spawn_scripted_server(vec![target.clone(), b"ERROR\n".to_vec()]);
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        server.set_target_binary(true);
+        assert_eq!(server.get_target_hash().unwrap().value.to_vec(), target);
+        assert!(matches!(
+            server.get_target_hash(),
+            Err(PowLockError::InvalidOperationWhenUnlocked)
+        ));
     }
 }
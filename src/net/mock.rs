@@ -0,0 +1,153 @@
+use crate::hash::{Sha256Hash, Sha256Hasher};
+use rustc_serialize::hex::FromHex;
+use std::io::prelude::*;
+use std::io::{BufRead, BufReader};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+
+const ERROR_LINE: &[u8] = b"ERROR\n";
+
+// a lock held in memory that speaks the device's protocol, for exercising
+// PowServer and the commands built on it without hardware. Every connection
+// shares the one lock
+pub struct MockLock {
+    listener: TcpListener,
+    challenge: Arc<Mutex<Option<(String, Sha256Hash)>>>, // base and target while locked
+}
+
+impl MockLock {
+    pub fn bind(addr: &str) -> std::io::Result<MockLock> {
+        Ok(MockLock {
+            listener: TcpListener::bind(addr)?,
+            challenge: Arc::new(Mutex::new(None)),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // handles each connection on its own thread, forever
+    pub fn run(self) -> () {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            let challenge = self.challenge.clone();
+            std::thread::spawn(move || handle_connection(stream, &challenge));
+        }
+    }
+}
+
+// answers commands until the connection closes. Each command is one
+// character; a lock request is followed by the target's 32 raw bytes, which
+// may contain a newline, so it's read by length rather than as a line
+fn handle_connection(stream: TcpStream, challenge: &Mutex<Option<(String, Sha256Hash)>>) -> () {
+    let mut writer = match stream.try_clone() {
+        Ok(writer) => writer,
+        Err(_) => return,
+    };
+    let mut reader = BufReader::new(stream);
+    loop {
+        let mut command = [0u8; 1];
+        if reader.read_exact(&mut command).is_err() {
+            return;
+        }
+        let mut target = [0u8; 32];
+        if command[0] == b'l' && reader.read_exact(&mut target).is_err() {
+            return;
+        }
+        let mut rest = vec![];
+        if reader.read_until(b'\n', &mut rest).is_err() {
+            return;
+        }
+        let rest = String::from_utf8_lossy(&rest);
+        let mut challenge = challenge.lock().unwrap();
+        let reply = match (command[0], &*challenge) {
+            (b'\r', _) | (b'\n', _) => continue,
+            (b'O', None) => b"1\n".to_vec(),
+            (b's', Some(_)) => b"1\n".to_vec(),
+            (b's', None) => b"0\n".to_vec(),
+            (b'b', Some((base, _))) => format!("{}\n", base).into_bytes(),
+            (b't', Some((_, target))) => format!("{}\n", target).into_bytes(),
+            (b'u', Some((base, target))) => match unlocks(base, target, rest.trim()) {
+                Some(true) => {
+                    *challenge = None;
+                    b"1\n".to_vec()
+                }
+                Some(false) => b"0\n".to_vec(),
+                None => ERROR_LINE.to_vec(),
+            },
+            (b'l', None) => {
+                let base = format!("{:016x}", rand::random::<u64>());
+                *challenge = Some((base.clone(), Sha256Hash::from_bytes(target)));
+                format!("{}\n", base).into_bytes()
+            }
+            _ => ERROR_LINE.to_vec(),
+        };
+        if writer.write_all(&reply).is_err() {
+            return;
+        }
+    }
+}
+
+// whether the nonce, as the hex bytes the lock was sent, solves the
+// challenge; the bytes are hashed after the base exactly as sent, whatever
+// order the client wrote them in. None if they aren't an 8 byte nonce
+fn unlocks(base: &str, target: &Sha256Hash, nonce_hex: &str) -> Option<bool> {
+    let nonce_bytes = nonce_hex.from_hex().ok().filter(|bytes| bytes.len() == 8)?;
+    let mut message = base.as_bytes().to_vec();
+    message.extend_from_slice(&nonce_bytes);
+    Some(Sha256Hasher::hash_bytes(&message).meets_target(target))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::MockLock;
+    use crate::hash::{HashWorkerFarm, Sha256Hash, Sha256Hasher};
+    use crate::net::{LockStatus, PowLockError, PowServer};
+    use std::str::FromStr;
+
+    fn spawn_mock_lock() -> String {
+        let lock = MockLock::bind("127.0.0.1:0").unwrap();
+        let port = lock.local_addr().unwrap().port();
+        std::thread::spawn(move || lock.run());
+        port.to_string()
+    }
+
+    #[test]
+    fn it_locks_and_unlocks_end_to_end() {
+        let port = spawn_mock_lock();
+        let mut server = PowServer::new("127.0.0.1".to_string(), port);
+        let target_hex = "0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        let target = Sha256Hash::from_str(&target_hex.to_string()).unwrap();
+
+        assert_eq!(server.get_status().unwrap(), LockStatus::Unlocked);
+        let base = server.lock(target_hex.to_string()).unwrap();
+        assert_eq!(server.get_status().unwrap(), LockStatus::Locked);
+        assert_eq!(server.get_base().unwrap(), base);
+        assert_eq!(server.get_target_hash().unwrap(), target);
+        assert!(matches!(
+            server.open(),
+            Err(PowLockError::InvalidOperationWhenLocked)
+        ));
+
+        let hasher = Sha256Hasher::new(base.clone().into_bytes());
+        let wrong_nonce = (0..).find(|&n| !hasher.verify(n, &target)).unwrap();
+        assert!(matches!(
+            server.unlock(wrong_nonce),
+            Err(PowLockError::Unsuccessful)
+        ));
+
+        let farm = HashWorkerFarm::new(base.into_bytes(), target, 1);
+        let solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        server.unlock_with_solution(&solution).unwrap();
+        assert_eq!(server.get_status().unwrap(), LockStatus::Unlocked);
+        assert!(matches!(
+            server.get_base(),
+            Err(PowLockError::InvalidOperationWhenUnlocked)
+        ));
+        server.open().unwrap();
+    }
+}
@@ -0,0 +1,202 @@
+use crate::hash::Nonce;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+// a set of nonces stored as sorted, non-overlapping, non-adjacent
+// half-open ranges [start, end)
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct NonceRanges {
+    ranges: Vec<(Nonce, Nonce)>,
+}
+
+impl NonceRanges {
+    pub fn new() -> NonceRanges {
+        NonceRanges { ranges: vec![] }
+    }
+
+    // every nonce except [start, end), so a resumed solve searches only that range
+    pub fn outside(start: Nonce, end: Nonce) -> NonceRanges {
+        let mut ranges = NonceRanges::new();
+        ranges.insert(0, start);
+        ranges.insert(end, std::u64::MAX);
+        ranges
+    }
+
+    pub fn ranges(&self) -> &[(Nonce, Nonce)] {
+        &self.ranges
+    }
+
+    pub fn insert(&mut self, start: Nonce, end: Nonce) -> () {
+        if start >= end {
+            return;
+        }
+        let mut merged = (start, end);
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        for &(s, e) in &self.ranges {
+            if e < merged.0 || s > merged.1 {
+                ranges.push((s, e));
+            } else {
+                merged = (std::cmp::min(s, merged.0), std::cmp::max(e, merged.1));
+            }
+        }
+        ranges.push(merged);
+        ranges.sort();
+        self.ranges = ranges;
+    }
+
+    pub fn contains(&self, nonce: Nonce) -> bool {
+        self.covering(nonce).is_some()
+    }
+
+    // the end of the range containing `nonce`, if any
+    fn covering(&self, nonce: Nonce) -> Option<Nonce> {
+        let i = match self.ranges.binary_search_by(|&(s, _)| s.cmp(&nonce)) {
+            Ok(i) => return Some(self.ranges[i].1),
+            Err(0) => return None,
+            Err(i) => i - 1,
+        };
+        match nonce < self.ranges[i].1 {
+            true => Some(self.ranges[i].1),
+            false => None,
+        }
+    }
+
+    // the first nonce at or after `nonce` that isn't in the set
+    pub fn next_uncovered(&self, nonce: Nonce) -> Nonce {
+        match self.covering(nonce) {
+            Some(end) => end, // ranges aren't adjacent, so `end` isn't covered
+            None => nonce,
+        }
+    }
+
+    // the number of nonces in [start, end) that aren't in the set
+    pub fn uncovered_count(&self, start: Nonce, end: Nonce) -> u64 {
+        let mut count = end.saturating_sub(start);
+        for &(s, e) in &self.ranges {
+            let overlap_start = std::cmp::max(s, start);
+            let overlap_end = std::cmp::min(e, end);
+            if overlap_start < overlap_end {
+                count -= overlap_end - overlap_start;
+            }
+        }
+        count
+    }
+
+    // divides [start, end) into `parts` consecutive spans, each holding an
+    // equal share of the nonces that aren't in the set; the last span takes
+    // the remainder and always ends at `end`
+    pub fn split_uncovered(&self, start: Nonce, end: Nonce, parts: u8) -> Vec<(Nonce, Nonce)> {
+        let share = self.uncovered_count(start, end) / parts as u64;
+        let mut spans = vec![];
+        let mut span_start = start;
+        for i in 0..parts {
+            if i + 1 == parts {
+                spans.push((span_start, end));
+                break;
+            }
+            let span_end = self.advance(span_start, share, end);
+            spans.push((span_start, span_end));
+            span_start = span_end;
+        }
+        spans
+    }
+
+    // the nonce after which `count` uncovered nonces from `start` have been passed
+    fn advance(&self, start: Nonce, count: u64, limit: Nonce) -> Nonce {
+        let mut position = start;
+        let mut remaining = count;
+        for &(s, e) in &self.ranges {
+            if e <= position {
+                continue;
+            }
+            if s >= limit {
+                break;
+            }
+            let gap = s.saturating_sub(position);
+            if gap >= remaining {
+                break;
+            }
+            remaining -= gap;
+            position = std::cmp::max(position, e);
+        }
+        std::cmp::min(position.saturating_add(remaining), limit)
+    }
+}
+
+// serialized as one `start-end` range per line
+impl std::fmt::Display for NonceRanges {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        for &(start, end) in &self.ranges {
+            writeln!(f, "{}-{}", start, end)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for NonceRanges {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut ranges = NonceRanges::new();
+        for line in s.lines().map(|l| l.trim()).filter(|l| !l.is_empty()) {
+            let mut bounds = line.splitn(2, '-');
+            let start = bounds.next().and_then(|b| b.parse::<Nonce>().ok());
+            let end = bounds.next().and_then(|b| b.parse::<Nonce>().ok());
+            match (start, end) {
+                (Some(start), Some(end)) => ranges.insert(start, end),
+                _ => return Err(format!("Invalid nonce range: {}", line)),
+            }
+        }
+        Ok(ranges)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NonceRanges;
+    use std::str::FromStr;
+
+    #[test]
+    fn it_merges_overlapping_and_adjacent_ranges() {
+        let mut ranges = NonceRanges::new();
+        ranges.insert(10, 20);
+        ranges.insert(30, 40);
+        ranges.insert(15, 30);
+        ranges.insert(50, 60);
+        assert_eq!(ranges.ranges(), &[(10, 40), (50, 60)]);
+        assert!(ranges.contains(10));
+        assert!(!ranges.contains(40));
+        assert_eq!(ranges.next_uncovered(12), 40);
+        assert_eq!(ranges.next_uncovered(45), 45);
+    }
+
+    #[test]
+    fn it_counts_uncovered_nonces() {
+        let mut ranges = NonceRanges::new();
+        ranges.insert(10, 20);
+        ranges.insert(30, 40);
+        assert_eq!(ranges.uncovered_count(0, 100), 80);
+        assert_eq!(ranges.uncovered_count(15, 35), 10);
+    }
+
+    #[test]
+    fn it_splits_uncovered_nonces_evenly() {
+        let mut ranges = NonceRanges::new();
+        ranges.insert(0, 10);
+        ranges.insert(20, 30);
+        let spans = ranges.split_uncovered(0, 100, 3);
+        assert_eq!(spans, vec![(0, 46), (46, 72), (72, 100)]);
+        assert_eq!(
+            NonceRanges::new().split_uncovered(0, 99, 3),
+            vec![(0, 33), (33, 66), (66, 99)]
+        );
+    }
+
+    #[test]
+    fn it_round_trips_through_a_string() {
+        let mut ranges = NonceRanges::new();
+        ranges.insert(0, 10);
+        ranges.insert(std::u64::MAX - 5, std::u64::MAX);
+        assert_eq!(NonceRanges::from_str(&ranges.to_string()), Ok(ranges));
+        assert!(NonceRanges::from_str("10-").is_err());
+    }
+}
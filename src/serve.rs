@@ -0,0 +1,312 @@
+use crate::hash::{HashWorkerFarm, Nonce, NonceByteOrder, Sha256Hash, SolveGoal, TNonce};
+use crate::ranges::NonceRanges;
+use serde::{Deserialize, Serialize};
+use std::io::prelude::*;
+use std::io::{BufRead, BufReader};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const MAX_BODY_BYTES: usize = 64 * 1024;
+// how long a client may go without sending anything before it's dropped
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// body of `POST /solve`
+#[derive(Debug, Deserialize)]
+pub struct SolveRequest {
+    pub base: String,
+    pub target: String,       // 64 hex characters
+    pub workers: Option<u8>,  // defaults to 1
+    pub start: Option<Nonce>, // only nonces in [start, end) are searched
+    pub end: Option<Nonce>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SolveResponse {
+    pub nonce: Nonce,
+    pub nonce_hex: String,
+    pub hash: String,
+    pub attempts: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct HashrateResponse {
+    hash_rate: Option<f64>, // H/s over every solve so far, none before the first
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+// hash attempts and time spent solving, across every request
+#[derive(Default)]
+struct Stats {
+    attempts: u64,
+    solving: Duration,
+}
+
+pub struct SolveService {
+    listener: TcpListener,
+    max_concurrent_solves: usize,
+    active_solves: Arc<AtomicUsize>,
+    stats: Arc<Mutex<Stats>>,
+}
+
+impl SolveService {
+    pub fn bind(addr: &str, max_concurrent_solves: usize) -> std::io::Result<SolveService> {
+        Ok(SolveService {
+            listener: TcpListener::bind(addr)?,
+            max_concurrent_solves: max_concurrent_solves,
+            active_solves: Arc::new(AtomicUsize::new(0)),
+            stats: Arc::new(Mutex::new(Stats::default())),
+        })
+    }
+
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    // handles each connection on its own thread, forever
+    pub fn run(self) -> () {
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(s) => s,
+                Err(_) => continue,
+            };
+            let max_concurrent_solves = self.max_concurrent_solves;
+            let active_solves = self.active_solves.clone();
+            let stats = self.stats.clone();
+            std::thread::spawn(move || {
+                handle_connection(stream, max_concurrent_solves, &active_solves, &stats)
+            });
+        }
+    }
+}
+
+fn handle_connection(
+    stream: TcpStream,
+    max_concurrent_solves: usize,
+    active_solves: &AtomicUsize,
+    stats: &Mutex<Stats>,
+) -> () {
+    if stream.set_read_timeout(Some(READ_TIMEOUT)).is_err() {
+        return;
+    }
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    if reader.read_line(&mut request_line).is_err() {
+        return;
+    }
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        match reader.read_line(&mut header) {
+            Ok(0) | Err(_) => return,
+            Ok(_) => {}
+        }
+        let header = header.trim();
+        if header.is_empty() {
+            break;
+        }
+        let mut parts = header.splitn(2, ':');
+        let name = parts.next().unwrap_or("").trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = parts.next().unwrap_or("").trim().parse().unwrap_or(0);
+        }
+    }
+    if content_length > MAX_BODY_BYTES {
+        let (status, response) = error(413, "Request body too large");
+        respond(&stream, status, &response);
+        return;
+    }
+    let mut body = vec![0u8; content_length];
+    if reader.read_exact(&mut body).is_err() {
+        return;
+    }
+
+    let mut words = request_line.split_whitespace();
+    let (status, response) = match (words.next(), words.next()) {
+        (Some("POST"), Some("/solve")) => {
+            // reserve a slot before solving so concurrent requests can't overshoot the limit
+            if active_solves.fetch_add(1, Ordering::SeqCst) >= max_concurrent_solves {
+                active_solves.fetch_sub(1, Ordering::SeqCst);
+                error(503, "Too many concurrent solves")
+            } else {
+                let result = solve(&body, stats);
+                active_solves.fetch_sub(1, Ordering::SeqCst);
+                result
+            }
+        }
+        (Some("GET"), Some("/hashrate")) => {
+            let stats = stats.lock().unwrap();
+            let hash_rate = match stats.attempts {
+                0 => None,
+                attempts => Some(attempts as f64 / stats.solving.as_secs_f64()),
+            };
+            (
+                200,
+                serde_json::to_string(&HashrateResponse {
+                    hash_rate: hash_rate,
+                })
+                .unwrap(),
+            )
+        }
+        _ => error(404, "Not found"),
+    };
+    respond(&stream, status, &response);
+}
+
+fn solve(body: &[u8], stats: &Mutex<Stats>) -> (u16, String) {
+    let request: SolveRequest = match serde_json::from_slice(body) {
+        Ok(r) => r,
+        Err(e) => return error(400, &format!("Invalid request: {}", e)),
+    };
+    let target = match Sha256Hash::from_str(&request.target) {
+        Ok(t) => t,
+        Err(e) => return error(400, &format!("Invalid target: {}", e)),
+    };
+    let start = request.start.unwrap_or(0);
+    let end = request.end.unwrap_or(std::u64::MAX);
+    if start >= end {
+        return error(400, "The range must contain at least one nonce");
+    }
+    // searching only [start, end) is the same as resuming a solve that
+    // already covered everything else
+    let farm = HashWorkerFarm::resume(
+        request.base.into_bytes(),
+        SolveGoal::Target(target),
+        request.workers.unwrap_or(1).max(1),
+        NonceRanges::outside(start, end),
+    );
+
+    let start_time = Instant::now();
+    // no progress bars: concurrent requests would garble the server's stderr
+    let result = Box::from(farm).solve_with_callback(|_| ()).solution();
+    let mut stats = stats.lock().unwrap();
+    stats.solving += start_time.elapsed();
+    match result {
+        Some(solution) => {
+            stats.attempts += solution.attempts;
+            let response = SolveResponse {
+                nonce: solution.nonce,
+                nonce_hex: solution.nonce.as_hex_bytes(NonceByteOrder::Little),
+                hash: solution.hash.to_string(),
+                attempts: solution.attempts,
+            };
+            (200, serde_json::to_string(&response).unwrap())
+        }
+        None => {
+            stats.attempts += end - start;
+            error(422, "No solution in the nonce range")
+        }
+    }
+}
+
+fn error(status: u16, message: &str) -> (u16, String) {
+    let response = ErrorResponse {
+        error: message.to_string(),
+    };
+    (status, serde_json::to_string(&response).unwrap())
+}
+
+fn respond(mut stream: &TcpStream, status: u16, body: &str) -> () {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        body
+    );
+    // the client may already have gone; there's no one to tell
+    let _ = stream.write_all(response.as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SolveService;
+    use crate::hash::{Sha256Hash, Sha256Hasher};
+    use std::io::prelude::*;
+    use std::net::TcpStream;
+    use std::str::FromStr;
+    use std::sync::atomic::Ordering;
+
+    fn request(addr: &str, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn it_solves_a_posted_challenge() {
+        let service = SolveService::bind("127.0.0.1:0", 1).unwrap();
+        let addr = service.local_addr().unwrap().to_string();
+        std::thread::spawn(move || service.run());
+
+        let target = "0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        let body = format!(
+            "{{\"base\": \"helloworld\", \"target\": \"{}\", \"workers\": 2}}",
+            target
+        );
+        let response = request(
+            &addr,
+            &format!(
+                "POST /solve HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        let (_, json) = response.split_once("\r\n\r\n").unwrap();
+        let solution: serde_json::Value = serde_json::from_str(json).unwrap();
+        let nonce = solution["nonce"].as_u64().unwrap();
+        let hash = Sha256Hasher::new(b"helloworld".to_vec()).hash_with_nonce(nonce);
+        assert!(hash < Sha256Hash::from_str(target).unwrap());
+        assert_eq!(solution["hash"], hash.to_string().as_str());
+
+        let response = request(&addr, "GET /hashrate HTTP/1.1\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    }
+
+    #[test]
+    fn it_refuses_solves_beyond_the_limits() {
+        let service = SolveService::bind("127.0.0.1:0", 1).unwrap();
+        let addr = service.local_addr().unwrap().to_string();
+        // as if another request were already solving
+        service.active_solves.fetch_add(1, Ordering::SeqCst);
+        std::thread::spawn(move || service.run());
+
+        let body = format!(
+            "{{\"base\": \"helloworld\", \"target\": \"{}\"}}",
+            "0f".repeat(32)
+        );
+        let response = request(
+            &addr,
+            &format!(
+                "POST /solve HTTP/1.1\r\nContent-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            ),
+        );
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"));
+
+        let response = request(
+            &addr,
+            "POST /solve HTTP/1.1\r\nContent-Length: 99999999999999\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+    }
+}
@@ -0,0 +1,126 @@
+use crate::hash::{HashSolution, Nonce, Sha256Hash, SolveGoal};
+use crate::ranges::NonceRanges;
+
+// the target math and range bookkeeping of a solve, for callers that hash
+// with their own hasher. Ranges of nonces are handed out with `next_range`
+// and each hash is reported back with `record`
+pub struct SolveSession {
+    goal: SolveGoal,
+    handed_out: NonceRanges,
+    attempts: u64,
+    solution: Option<HashSolution>, // the first solution recorded
+}
+
+impl SolveSession {
+    pub fn new(goal: SolveGoal) -> SolveSession {
+        SolveSession::resume(goal, NonceRanges::new())
+    }
+
+    // a session that won't hand out the nonces in `covered`, ex: a checkpoint
+    pub fn resume(goal: SolveGoal, covered: NonceRanges) -> SolveSession {
+        SolveSession {
+            goal: goal,
+            handed_out: covered,
+            attempts: 0,
+            solution: None,
+        }
+    }
+
+    // the next `count` nonces not yet handed out, as [start, end); fewer when
+    // they run into nonces already handed out. None once there are none left
+    pub fn next_range(&mut self, count: u64) -> Option<(Nonce, Nonce)> {
+        let start = self.handed_out.next_uncovered(0);
+        if start == std::u64::MAX || count == 0 {
+            return None;
+        }
+        let next_covered = self
+            .handed_out
+            .ranges()
+            .iter()
+            .map(|&(s, _)| s)
+            .find(|&s| s > start)
+            .unwrap_or(std::u64::MAX);
+        let end = start.saturating_add(count).min(next_covered);
+        self.handed_out.insert(start, end);
+        Some((start, end))
+    }
+
+    // counts an attempt and returns whether `hash`, the hash of `nonce`, is a solution
+    pub fn record(&mut self, nonce: Nonce, hash: &Sha256Hash) -> bool {
+        self.attempts += 1;
+        if !self.goal.is_met_by(hash) {
+            return false;
+        }
+        if self.solution.is_none() {
+            self.solution = Some(HashSolution {
+                nonce: nonce,
+                nonce_high: 0,
+                attempts: self.attempts,
+                hash: hash.clone(),
+            });
+        }
+        true
+    }
+
+    pub fn attempts(&self) -> u64 {
+        self.attempts
+    }
+
+    pub fn solution(&self) -> Option<&HashSolution> {
+        self.solution.as_ref()
+    }
+
+    // every nonce handed out so far, whether or not it's been recorded
+    pub fn handed_out(&self) -> &NonceRanges {
+        &self.handed_out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SolveSession;
+    use crate::hash::{Sha256Hash, Sha256Hasher, SolveGoal};
+    use crate::ranges::NonceRanges;
+    use std::str::FromStr;
+
+    #[test]
+    fn it_finds_a_solution_with_external_hashes() {
+        let target = Sha256Hash::from_str(
+            &"00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+        )
+        .unwrap();
+        let mut session = SolveSession::new(SolveGoal::Target(target));
+        let hasher = Sha256Hasher::new(b"helloworld".to_vec());
+        let mut solved = None;
+        while solved.is_none() {
+            let (start, end) = session.next_range(64).unwrap();
+            assert_eq!(end - start, 64);
+            for nonce in start..end {
+                if session.record(nonce, &hasher.hash_with_nonce(nonce)) {
+                    solved = Some(nonce);
+                    break;
+                }
+            }
+        }
+        // the same first solution the farm finds with one worker
+        let solution = session.solution().unwrap();
+        assert_eq!(solution.nonce, 152);
+        assert_eq!(solution.attempts, 153);
+        assert_eq!(session.attempts(), 153);
+        assert_eq!(session.handed_out().ranges(), &[(0, 192)]);
+    }
+
+    #[test]
+    fn it_hands_out_only_unsearched_nonces() {
+        let mut covered = NonceRanges::new();
+        covered.insert(10, 20);
+        covered.insert(30, std::u64::MAX);
+        let goal = SolveGoal::Prefix(vec![0]);
+        let mut session = SolveSession::resume(goal, covered);
+        assert_eq!(session.next_range(8), Some((0, 8)));
+        assert_eq!(session.next_range(8), Some((8, 10)));
+        assert_eq!(session.next_range(8), Some((20, 28)));
+        assert_eq!(session.next_range(8), Some((28, 30)));
+        assert_eq!(session.next_range(8), None);
+    }
+}
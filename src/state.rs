@@ -0,0 +1,205 @@
+use crate::hash::{Sha256Hash, Sha256Hasher, SolveGoal};
+use crate::ranges::NonceRanges;
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use rustc_serialize::base64::{FromBase64, ToBase64, STANDARD};
+use serde::{Deserialize, Serialize};
+use std::io::Read;
+use std::path::Path;
+use std::str::FromStr;
+
+// the first byte of a resume token, so the format can change without old
+// tokens being misread
+const TOKEN_VERSION: u8 = 1;
+
+// everything needed to continue a solve exactly where an earlier one stopped
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SolveState {
+    pub base: Vec<u8>,
+    pub goal: String,         // the goal's display form, ex: a target in hex
+    pub covered: NonceRanges, // nonces already searched
+    pub attempts: u64,        // accumulated over every session
+    pub elapsed_ms: u64,      // accumulated over every session
+}
+
+impl SolveState {
+    pub fn new(base: Vec<u8>, goal: &SolveGoal) -> SolveState {
+        SolveState {
+            base: base,
+            goal: goal.to_string(),
+            covered: NonceRanges::new(),
+            attempts: 0,
+            elapsed_ms: 0,
+        }
+    }
+
+    pub fn goal(&self) -> Result<SolveGoal, String> {
+        SolveGoal::from_str(&self.goal)
+    }
+
+    pub fn load(path: &Path) -> Result<SolveState, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path.display(), e))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| format!("Invalid solve state in {}: {}", path.display(), e))
+    }
+
+    // writes to a temporary file first so an interrupted save never
+    // clobbers the previous state
+    pub fn save(&self, path: &Path) -> std::io::Result<()> {
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&temp_path, path)
+    }
+
+    // a compact alternative to a state file: the version, the base's SHA-256
+    // rather than the base itself, the goal, the attempts and elapsed time,
+    // then the covered ranges, all big endian and base64 encoded
+    pub fn to_token(&self) -> Result<String, String> {
+        let mut bytes = vec![TOKEN_VERSION];
+        bytes.extend_from_slice(&Sha256Hasher::hash_bytes(&self.base).value);
+        match self.goal()? {
+            SolveGoal::Target(target) => {
+                bytes.push(0);
+                bytes.extend_from_slice(&target.value);
+            }
+            SolveGoal::Prefix(prefix) => {
+                bytes.push(1);
+                bytes.push(prefix.len() as u8);
+                bytes.extend_from_slice(&prefix);
+            }
+        }
+        bytes.write_u64::<BigEndian>(self.attempts).unwrap();
+        bytes.write_u64::<BigEndian>(self.elapsed_ms).unwrap();
+        bytes
+            .write_u32::<BigEndian>(self.covered.ranges().len() as u32)
+            .unwrap();
+        for &(start, end) in self.covered.ranges() {
+            bytes.write_u64::<BigEndian>(start).unwrap();
+            bytes.write_u64::<BigEndian>(end).unwrap();
+        }
+        Ok(bytes.to_base64(STANDARD))
+    }
+
+    // the token only identifies the base, so it has to be given again
+    pub fn from_token(token: &str, base: Vec<u8>) -> Result<SolveState, String> {
+        let bytes = token
+            .trim()
+            .from_base64()
+            .map_err(|e| format!("Invalid resume token: {}", e))?;
+        let truncated = |_| "Invalid resume token: too short".to_string();
+        let mut reader = &bytes[..];
+        let version = reader.read_u8().map_err(truncated)?;
+        if version != TOKEN_VERSION {
+            return Err(format!("Unsupported resume token version {}", version));
+        }
+        let mut digest = [0u8; 32];
+        reader.read_exact(&mut digest).map_err(truncated)?;
+        if Sha256Hasher::hash_bytes(&base).value != digest {
+            return Err("The resume token is for a different base".to_string());
+        }
+        let goal = match reader.read_u8().map_err(truncated)? {
+            0 => {
+                let mut target = [0u8; 32];
+                reader.read_exact(&mut target).map_err(truncated)?;
+                SolveGoal::Target(Sha256Hash { value: target })
+            }
+            1 => {
+                let mut prefix = vec![0u8; reader.read_u8().map_err(truncated)? as usize];
+                reader.read_exact(&mut prefix).map_err(truncated)?;
+                SolveGoal::Prefix(prefix)
+            }
+            kind => return Err(format!("Invalid resume token: unknown goal {}", kind)),
+        };
+        let mut state = SolveState::new(base, &goal);
+        state.attempts = reader.read_u64::<BigEndian>().map_err(truncated)?;
+        state.elapsed_ms = reader.read_u64::<BigEndian>().map_err(truncated)?;
+        for _ in 0..reader.read_u32::<BigEndian>().map_err(truncated)? {
+            let start = reader.read_u64::<BigEndian>().map_err(truncated)?;
+            let end = reader.read_u64::<BigEndian>().map_err(truncated)?;
+            state.covered.insert(start, end);
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SolveState;
+    use crate::hash::{HashWorkerFarm, Sha256Hash, SolveGoal};
+    use crate::ranges::NonceRanges;
+    use std::str::FromStr;
+
+    #[test]
+    fn it_accumulates_progress_when_continuing_a_saved_solve() {
+        let path = std::env::temp_dir().join(format!("pow_key_state_{}", std::process::id()));
+        let base = b"helloworld".to_vec();
+        // practically impossible, so every solve stops at its attempt limit
+        let goal = SolveGoal::Target(
+            Sha256Hash::from_str(
+                &"0000000000000100000000000000000000000000000000000000000000000000".to_string(),
+            )
+            .unwrap(),
+        );
+
+        let mut farm = HashWorkerFarm::new_with_goal(base.clone(), goal.clone(), 1);
+        farm.set_max_attempts(Some(100));
+        farm.set_save_on_exit(path.clone(), SolveState::new(base.clone(), &goal));
+        assert!(HashWorkerFarm::solve(Box::from(farm)).is_none());
+        let saved = SolveState::load(&path).unwrap();
+        assert_eq!(saved.base, base);
+        assert_eq!(saved.goal().unwrap().to_string(), goal.to_string());
+        assert_eq!(saved.attempts, 100);
+        assert_eq!(saved.covered.ranges(), &[(0, 100)]);
+
+        let mut farm = HashWorkerFarm::resume(
+            saved.base.clone(),
+            saved.goal().unwrap(),
+            2,
+            saved.covered.clone(),
+        );
+        farm.set_max_attempts(Some(50));
+        farm.set_save_on_exit(path.clone(), saved.clone());
+        assert!(HashWorkerFarm::solve(Box::from(farm)).is_none());
+        let continued = SolveState::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(continued.attempts, 150);
+        assert!(continued.elapsed_ms >= saved.elapsed_ms);
+        assert_eq!(continued.covered.uncovered_count(0, 100), 0);
+        let searched: u64 = continued.covered.ranges().iter().map(|(s, e)| e - s).sum();
+        assert_eq!(searched, 150);
+    }
+
+    #[test]
+    fn it_round_trips_a_resume_token() {
+        let goal = SolveGoal::Target(
+            Sha256Hash::from_str(
+                &"000fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff".to_string(),
+            )
+            .unwrap(),
+        );
+        let mut covered = NonceRanges::new();
+        covered.insert(0, 1000);
+        covered.insert(1 << 40, (1 << 40) + 500);
+        let state = SolveState {
+            base: b"helloworld".to_vec(),
+            goal: goal.to_string(),
+            covered: covered,
+            attempts: 1500,
+            elapsed_ms: 42,
+        };
+        let token = state.to_token().unwrap();
+        assert_eq!(
+            SolveState::from_token(&token, b"helloworld".to_vec()).unwrap(),
+            state
+        );
+        assert!(SolveState::from_token(&token, b"goodbyeworld".to_vec()).is_err());
+        assert!(SolveState::from_token(&token[..20], b"helloworld".to_vec()).is_err());
+
+        let prefix = SolveState::new(b"helloworld".to_vec(), &SolveGoal::Prefix(vec![0, 1]));
+        let token = prefix.to_token().unwrap();
+        assert_eq!(
+            SolveState::from_token(&token, b"helloworld".to_vec()).unwrap(),
+            prefix
+        );
+    }
+}
@@ -0,0 +1,336 @@
+use crate::hash::{HashAlgorithm, Sha256Hash};
+use rustc_serialize::hex::{FromHex, ToHex};
+use std::str::FromStr;
+
+// the different ways a target can be described on the command line
+#[derive(Debug, Clone, PartialEq)]
+pub enum TargetSpec {
+    Hex(String),          // the target itself as 64 hex characters
+    Difficulty(u64),      // bitcoin style difficulty relative to the difficulty 1 target
+    LeadingZeroBits(u32), // any hash with at least this many leading zero bits solves it
+    NBits(u32),           // bitcoin style compact representation
+    Duration {
+        duration: String, // plain text duration, ex: 4hr 25min
+        hash_rate: u64,   // hashes/s
+    },
+    Confidence {
+        confidence: f64, // probability of solving within the duration, between 0 and 1
+        duration: String,
+        hash_rate: u64,
+    },
+}
+
+impl TargetSpec {
+    pub fn resolve(&self) -> Result<Sha256Hash, String> {
+        match self {
+            TargetSpec::Hex(hex) => Sha256Hash::from_str(hex).map_err(|e| e.to_string()),
+            TargetSpec::Difficulty(difficulty) => {
+                if *difficulty == 0 {
+                    return Err("Difficulty must be at least 1".to_string());
+                }
+                Ok(Sha256Hash::target_for_difficulty(*difficulty))
+            }
+            TargetSpec::LeadingZeroBits(bits) => {
+                if *bits > 255 {
+                    return Err("At most 255 leading zero bits can be required".to_string());
+                }
+                let mut value: [u8; 32] = [0; 32];
+                if *bits == 0 {
+                    value = [0xff; 32];
+                } else {
+                    // 2^(256 - bits): every hash below it has `bits` leading zeros
+                    let bit_index = 256 - *bits as usize;
+                    value[31 - bit_index / 8] = 1 << (bit_index % 8);
+                }
+                Ok(Sha256Hash { value: value })
+            }
+            TargetSpec::NBits(n_bits) => {
+                let exponent = (n_bits >> 24) as usize;
+                let mantissa = n_bits & 0x007fffff;
+                if n_bits & 0x00800000 != 0 {
+                    return Err("nBits targets cannot be negative".to_string());
+                }
+                if exponent > 32 {
+                    return Err("nBits exponent must be at most 32".to_string());
+                }
+                let mut value: [u8; 32] = [0; 32];
+                let mantissa_bytes = mantissa.to_be_bytes();
+                // the mantissa is the 3 most significant bytes of an `exponent` byte number
+                for i in 0..3 {
+                    if exponent > i {
+                        value[32 - (exponent - i)] = mantissa_bytes[i + 1];
+                    }
+                }
+                Ok(Sha256Hash { value: value })
+            }
+            TargetSpec::Duration {
+                duration,
+                hash_rate,
+            } => {
+                parse_duration(duration)?;
+                Ok(Sha256Hash::target_for_duration(
+                    duration.to_string(),
+                    *hash_rate,
+                ))
+            }
+            TargetSpec::Confidence {
+                confidence,
+                duration,
+                hash_rate,
+            } => {
+                if !(*confidence > 0.0 && *confidence < 1.0) {
+                    return Err("Confidence must be between 0 and 1".to_string());
+                }
+                let attempts = parse_duration(duration)?.as_secs() as f64 * *hash_rate as f64;
+                // P(solved within n attempts) = 1 - (1 - p)^n, so for a confidence c
+                // the expected attempts 1/p are approximately n / -ln(1 - c)
+                let expected_attempts = attempts / -(1.0 - confidence).ln();
+                if expected_attempts < 1.0 {
+                    return Err("Duration is too short to solve with that confidence".to_string());
+                }
+                Ok(Sha256Hash::target_for_hash_attempts_expected(
+                    expected_attempts as u64,
+                ))
+            }
+        }
+    }
+}
+
+// the target for a solution that also has at least `bits` leading zero bits,
+// ex: to bank margin below the lock's target. A hash meets both exactly when
+// it's below the lower of the two targets
+pub fn with_min_leading_zeros(target: Sha256Hash, bits: u32) -> Result<Sha256Hash, String> {
+    let zeros_target = TargetSpec::LeadingZeroBits(bits).resolve()?;
+    Ok(std::cmp::min(target, zeros_target))
+}
+
+// the number of bytes in the hashes an algorithm produces, by name
+pub fn digest_width(algorithm: &str) -> Result<usize, String> {
+    if algorithm == "sha512" {
+        return Ok(64);
+    }
+    match HashAlgorithm::ALL
+        .iter()
+        .any(|a| a.to_string() == algorithm)
+    {
+        true => Ok(32),
+        false => Err(format!("Unknown algorithm {}", algorithm)),
+    }
+}
+
+// the expected number of attempts for a big-endian target of any width
+pub fn expected_attempts(target: &[u8]) -> f64 {
+    let value = target
+        .iter()
+        .fold(0.0, |value, &byte| value * 256.0 + byte as f64);
+    2f64.powi(8 * target.len() as i32) / value
+}
+
+// rescales a target from one hash width to another so it takes the same
+// number of attempts on average: the target's share of all possible hashes
+// stays the same
+pub fn match_difficulty(
+    from_target: &str,
+    from_width: usize,
+    to_width: usize,
+) -> Result<String, String> {
+    let target = from_target
+        .from_hex()
+        .map_err(|e| format!("Invalid target: {:?}", e))?;
+    if target.len() != from_width {
+        return Err(format!(
+            "Expected a target of {} hex characters",
+            from_width * 2
+        ));
+    }
+    let mut matched = target.clone();
+    matched.resize(to_width, 0);
+    if matched.iter().all(|&byte| byte == 0) {
+        return Err("The target is too hard to express in the narrower hash".to_string());
+    }
+    Ok(matched.to_hex())
+}
+
+// what running a solve is expected to take: the time, and the energy and
+// money spent drawing a fixed amount of power for that long
+#[derive(Debug, PartialEq)]
+pub struct CostEstimate {
+    pub expected_time: std::time::Duration,
+    pub energy_kwh: f64,
+    pub cost: f64, // in whatever currency the price is in
+}
+
+// estimates the cost of solving for `target` at `hash_rate` H/s on hardware
+// drawing `watts`, with electricity at `price_per_kwh`
+pub fn estimate_cost(
+    target: &Sha256Hash,
+    hash_rate: u64,
+    watts: f64,
+    price_per_kwh: f64,
+) -> Result<CostEstimate, String> {
+    if hash_rate == 0 {
+        return Err("The hashrate must be greater than 0".to_string());
+    }
+    if watts < 0.0 || price_per_kwh < 0.0 {
+        return Err("The power draw and price can't be negative".to_string());
+    }
+    let seconds = target.expected_attempts_to_solve() as f64 / hash_rate as f64;
+    let energy_kwh = watts * seconds / 3600.0 / 1000.0;
+    Ok(CostEstimate {
+        expected_time: std::time::Duration::try_from_secs_f64(seconds)
+            .unwrap_or(std::time::Duration::MAX),
+        energy_kwh: energy_kwh,
+        cost: energy_kwh * price_per_kwh,
+    })
+}
+
+fn parse_duration(duration: &str) -> Result<std::time::Duration, String> {
+    match duration.parse::<humantime::Duration>() {
+        Ok(d) => Ok(d.into()),
+        Err(e) => Err(format!("Invalid duration {}: {}", duration, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        estimate_cost, expected_attempts, match_difficulty, with_min_leading_zeros, TargetSpec,
+    };
+    use crate::hash::{HashWorkerFarm, Sha256Hash, DIFFICULTY_1_TARGET};
+    use rustc_serialize::hex::FromHex;
+    use std::str::FromStr;
+
+    fn hash(hex: &str) -> Sha256Hash {
+        Sha256Hash::from_str(hex).unwrap()
+    }
+
+    #[test]
+    fn it_requires_leading_zeros_below_an_easier_target() {
+        let target = hash("0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff");
+        let stricter = with_min_leading_zeros(target.clone(), 10).unwrap();
+        assert_eq!(
+            stricter,
+            hash("0040000000000000000000000000000000000000000000000000000000000000")
+        );
+        let farm = HashWorkerFarm::new(b"helloworld".to_vec(), stricter, 1);
+        let solution = HashWorkerFarm::solve(Box::from(farm)).unwrap();
+        assert!(solution.hash.meets_target(&target));
+        assert!(solution.hash.leading_zero_bits() >= 10);
+        // the target is kept when it's already the stricter one
+        assert_eq!(with_min_leading_zeros(target.clone(), 2).unwrap(), target);
+    }
+
+    #[test]
+    fn it_resolves_hex_targets() {
+        let hex = "00000000ffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+        assert_eq!(TargetSpec::Hex(hex.to_string()).resolve(), Ok(hash(hex)));
+        assert!(TargetSpec::Hex("aa00bb".to_string()).resolve().is_err());
+    }
+
+    #[test]
+    fn it_resolves_difficulty_targets() {
+        assert_eq!(
+            TargetSpec::Difficulty(1).resolve(),
+            Ok(hash(
+                "00000000ffff0000000000000000000000000000000000000000000000000000"
+            ))
+        );
+        assert!(TargetSpec::Difficulty(0).resolve().is_err());
+    }
+
+    #[test]
+    fn it_resolves_leading_zero_bit_targets() {
+        assert_eq!(
+            TargetSpec::LeadingZeroBits(0).resolve(),
+            Ok(hash(
+                "ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"
+            ))
+        );
+        assert_eq!(
+            TargetSpec::LeadingZeroBits(12).resolve(),
+            Ok(hash(
+                "0010000000000000000000000000000000000000000000000000000000000000"
+            ))
+        );
+    }
+
+    #[test]
+    fn it_resolves_n_bits_targets() {
+        assert_eq!(
+            TargetSpec::NBits(0x1d00ffff).resolve(),
+            Ok(hash(
+                "00000000ffff0000000000000000000000000000000000000000000000000000"
+            ))
+        );
+        assert!(TargetSpec::NBits(0x1d80ffff).resolve().is_err());
+    }
+
+    #[test]
+    fn it_resolves_duration_targets() {
+        assert_eq!(
+            TargetSpec::Duration {
+                duration: "10s".to_string(),
+                hash_rate: 10
+            }
+            .resolve(),
+            Ok(Sha256Hash::target_for_hash_attempts_expected(100))
+        );
+        assert!(TargetSpec::Duration {
+            duration: "soon".to_string(),
+            hash_rate: 10
+        }
+        .resolve()
+        .is_err());
+    }
+
+    #[test]
+    fn it_resolves_confidence_targets() {
+        // 1 - e^-1 of solves finish within the expected number of attempts
+        let confidence = 1.0 - (-1.0f64).exp();
+        let target = TargetSpec::Confidence {
+            confidence: confidence,
+            duration: "100s".to_string(),
+            hash_rate: 100,
+        }
+        .resolve()
+        .unwrap();
+        let expected = target.expected_attempts_to_solve();
+        assert!((9_990..=10_010).contains(&expected));
+    }
+
+    #[test]
+    fn it_matches_a_difficulty_across_hash_widths() {
+        assert_eq!(
+            match_difficulty(DIFFICULTY_1_TARGET, 32, 32),
+            Ok(DIFFICULTY_1_TARGET.to_string())
+        );
+        let sha512_target = match_difficulty(DIFFICULTY_1_TARGET, 32, 64)
+            .unwrap()
+            .from_hex()
+            .unwrap();
+        assert_eq!(sha512_target.len(), 64);
+        let sha256_attempts = expected_attempts(&DIFFICULTY_1_TARGET.from_hex().unwrap());
+        let sha512_attempts = expected_attempts(&sha512_target);
+        assert!((sha512_attempts / sha256_attempts - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn it_estimates_the_cost_of_a_solve() {
+        // about 2^20 attempts expected
+        let target = TargetSpec::LeadingZeroBits(20).resolve().unwrap();
+        // which take 1024s at 1024 H/s, and 3600 W for 1024s is 1.024 kWh
+        let estimate = estimate_cost(&target, 1024, 3600.0, 0.25).unwrap();
+        assert!((estimate.expected_time.as_secs_f64() - 1024.0).abs() < 0.01);
+        assert!((estimate.energy_kwh - 1.024).abs() < 1e-5);
+        assert!((estimate.cost - 0.256).abs() < 1e-5);
+        assert!(estimate_cost(&target, 0, 500.0, 0.2).is_err());
+        assert!(estimate_cost(&target, 1024, -1.0, 0.2).is_err());
+        // too long to represent, or impossible, saturates rather than panicking
+        let hard = TargetSpec::LeadingZeroBits(200).resolve().unwrap();
+        let estimate = estimate_cost(&hard, 1, 10.0, 0.1).unwrap();
+        assert_eq!(estimate.expected_time, std::time::Duration::MAX);
+        let zero = hash(&"00".repeat(32));
+        let estimate = estimate_cost(&zero, 1, 10.0, 0.1).unwrap();
+        assert_eq!(estimate.expected_time, std::time::Duration::MAX);
+    }
+}
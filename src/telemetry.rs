@@ -0,0 +1,235 @@
+// pushes solve metrics to an OpenTelemetry collector with OTLP over HTTP,
+// using the JSON encoding so no protobuf or gRPC stack is needed
+use serde::Serialize;
+use std::io::prelude::*;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// how long to wait for the collector; telemetry must never hold up a solve
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+pub struct SolveMetrics {
+    pub duration: Duration,
+    pub attempts: u64,
+    pub solved: bool,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct MetricsRequest {
+    resource_metrics: Vec<ResourceMetrics>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceMetrics {
+    resource: Resource,
+    scope_metrics: Vec<ScopeMetrics>,
+}
+
+#[derive(Serialize)]
+struct Resource {
+    attributes: Vec<Attribute>,
+}
+
+#[derive(Serialize)]
+struct ScopeMetrics {
+    scope: Scope,
+    metrics: Vec<Metric>,
+}
+
+#[derive(Serialize)]
+struct Scope {
+    name: String,
+}
+
+#[derive(Serialize)]
+struct Metric {
+    name: String,
+    unit: String,
+    gauge: Gauge,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Gauge {
+    data_points: Vec<DataPoint>,
+}
+
+// OTLP's JSON encoding writes 64 bit integers as strings
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DataPoint {
+    time_unix_nano: String,
+    as_double: f64,
+    attributes: Vec<Attribute>,
+}
+
+#[derive(Serialize)]
+struct Attribute {
+    key: String,
+    value: AttributeValue,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct AttributeValue {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    string_value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bool_value: Option<bool>,
+}
+
+fn metric(name: &str, unit: &str, value: f64, time: &str, solved: bool) -> Metric {
+    Metric {
+        name: name.to_string(),
+        unit: unit.to_string(),
+        gauge: Gauge {
+            data_points: vec![DataPoint {
+                time_unix_nano: time.to_string(),
+                as_double: value,
+                attributes: vec![Attribute {
+                    key: "solved".to_string(),
+                    value: AttributeValue {
+                        string_value: None,
+                        bool_value: Some(solved),
+                    },
+                }],
+            }],
+        },
+    }
+}
+
+// the body of an OTLP/HTTP metrics export for one solve
+pub fn metrics_payload(metrics: &SolveMetrics, time: SystemTime) -> String {
+    let time = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos()
+        .to_string();
+    let seconds = metrics.duration.as_secs_f64();
+    let hash_rate = match seconds > 0.0 {
+        true => metrics.attempts as f64 / seconds,
+        false => 0.0,
+    };
+    let request = MetricsRequest {
+        resource_metrics: vec![ResourceMetrics {
+            resource: Resource {
+                attributes: vec![Attribute {
+                    key: "service.name".to_string(),
+                    value: AttributeValue {
+                        string_value: Some("pow_key".to_string()),
+                        bool_value: None,
+                    },
+                }],
+            },
+            scope_metrics: vec![ScopeMetrics {
+                scope: Scope {
+                    name: "pow_key".to_string(),
+                },
+                metrics: vec![
+                    metric("solve.duration", "s", seconds, &time, metrics.solved),
+                    metric(
+                        "solve.attempts",
+                        "{attempt}",
+                        metrics.attempts as f64,
+                        &time,
+                        metrics.solved,
+                    ),
+                    metric(
+                        "solve.hashrate",
+                        "{hash}/s",
+                        hash_rate,
+                        &time,
+                        metrics.solved,
+                    ),
+                ],
+            }],
+        }],
+    };
+    serde_json::to_string(&request).unwrap()
+}
+
+// posts the metrics to `endpoint`, ex: http://localhost:4318
+pub fn export(endpoint: &str, metrics: &SolveMetrics) -> Result<(), String> {
+    let address = endpoint
+        .strip_prefix("http://")
+        .ok_or_else(|| format!("Only http:// endpoints are supported: {}", endpoint))?;
+    let (host, base_path) = match address.find('/') {
+        Some(i) => address.split_at(i),
+        None => (address, ""),
+    };
+    let socket_address = host
+        .to_socket_addrs()
+        .map_err(|e| e.to_string())?
+        .next()
+        .ok_or_else(|| format!("Unable to resolve {}", host))?;
+    let mut stream =
+        TcpStream::connect_timeout(&socket_address, TIMEOUT).map_err(|e| e.to_string())?;
+    stream
+        .set_write_timeout(Some(TIMEOUT))
+        .and_then(|_| stream.set_read_timeout(Some(TIMEOUT)))
+        .map_err(|e| e.to_string())?;
+    let body = metrics_payload(metrics, SystemTime::now());
+    let request = format!(
+        "POST {}/v1/metrics HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        base_path.trim_end_matches('/'),
+        host,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(request.as_bytes())
+        .map_err(|e| e.to_string())?;
+    let mut response = String::new();
+    // the status line is all we need
+    let _ = stream.read_to_string(&mut response);
+    match response.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        Some(status) => Err(format!("The collector responded with {}", status)),
+        None => Err("The collector didn't respond".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{export, metrics_payload, SolveMetrics};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn it_builds_a_metrics_payload() {
+        let metrics = SolveMetrics {
+            duration: Duration::from_secs(4),
+            attempts: 1000,
+            solved: true,
+        };
+        let payload = metrics_payload(&metrics, UNIX_EPOCH + Duration::from_secs(1));
+        let payload: serde_json::Value = serde_json::from_str(&payload).unwrap();
+        let scope = &payload["resourceMetrics"][0]["scopeMetrics"][0];
+        assert_eq!(scope["scope"]["name"], "pow_key");
+        let expected = [
+            ("solve.duration", 4.0),
+            ("solve.attempts", 1000.0),
+            ("solve.hashrate", 250.0),
+        ];
+        for (i, &(name, value)) in expected.iter().enumerate() {
+            let metric = &scope["metrics"][i];
+            assert_eq!(metric["name"], name);
+            let point = &metric["gauge"]["dataPoints"][0];
+            assert_eq!(point["asDouble"].as_f64(), Some(value));
+            assert_eq!(point["timeUnixNano"], "1000000000");
+            assert_eq!(point["attributes"][0]["value"]["boolValue"], true);
+        }
+    }
+
+    #[test]
+    fn it_fails_quietly_without_a_collector() {
+        let metrics = SolveMetrics {
+            duration: Duration::from_secs(1),
+            attempts: 1,
+            solved: false,
+        };
+        assert!(export("http://127.0.0.1:1", &metrics).is_err());
+        assert!(export("https://localhost:4318", &metrics).is_err());
+    }
+}
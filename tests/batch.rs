@@ -0,0 +1,47 @@
+use std::env;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// each challenge's result is written as it's solved, not once the batch is
+// done. Rust's stdout is line buffered, so this doesn't depend on the
+// explicit flushes in solve
+#[test]
+fn it_reports_each_solution_before_the_next_search() {
+    // the first challenge is solved at once, the second never is
+    let batch = env::temp_dir().join(format!("pow_key_batch_{}.csv", std::process::id()));
+    fs::write(
+        &batch,
+        "base,target\n\
+         helloworld,00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff\n\
+         helloworld,0000000000000100000000000000000000000000000000000000000000000000\n",
+    )
+    .unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&["solve", "--batch", batch.to_str().unwrap()])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let stdout = child.stdout.take().unwrap();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in BufReader::new(stdout).lines() {
+            let _ = sender.send(line.unwrap());
+        }
+    });
+    let solved = loop {
+        match receiver.recv_timeout(Duration::from_secs(30)) {
+            Ok(line) if line.starts_with("Solved with nonce") => break Some(line),
+            Ok(_) => continue,
+            Err(_) => break None,
+        }
+    };
+    child.kill().unwrap();
+    child.wait().unwrap();
+    fs::remove_file(&batch).unwrap();
+    assert!(solved.is_some());
+}
@@ -0,0 +1,49 @@
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[test]
+fn it_gives_up_on_a_target_too_hard_for_the_deadline() {
+    let start_time = Instant::now();
+    let output = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&[
+            "solve",
+            "-b",
+            "helloworld",
+            "-t",
+            "0000000000000100000000000000000000000000000000000000000000000000",
+            "--give-up-if-slower-than",
+            "1h",
+        ])
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Giving up: the solve is projected to take"));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "No solution found: gave up as too slow\n"
+    );
+    // the hashrate is measured for a second before giving up
+    assert!(start_time.elapsed() < Duration::from_secs(30));
+}
+
+#[test]
+fn it_reports_the_watchdog_as_the_reason_in_json() {
+    let output = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&[
+            "solve",
+            "-b",
+            "helloworld",
+            "-t",
+            "0000000000000100000000000000000000000000000000000000000000000000",
+            "--give-up-if-slower-than",
+            "1h",
+            "--format",
+            "json",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "{\"solution\":null,\"reason\":\"watchdog\"}\n"
+    );
+}
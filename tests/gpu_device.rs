@@ -0,0 +1,26 @@
+use std::process::Command;
+
+fn solve(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&["solve", "-b", "helloworld", "-t", &"0f".repeat(32)])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn it_rejects_options_the_gpu_would_ignore() {
+    for option in &[
+        ["--start", "5"],
+        ["--deadline", "1h"],
+        ["--checkpoint", "x"],
+    ] {
+        let output = solve(&["--device", "gpu", option[0], option[1]]);
+        assert!(!output.status.success(), "accepted {}", option[0]);
+        assert!(output.stdout.is_empty());
+    }
+    // on the cpu, which needn't be given, they're fine
+    let output = solve(&["--start", "5", "--count", "100000"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Solved with nonce"));
+}
@@ -0,0 +1,54 @@
+#![cfg(unix)]
+
+use std::io::Read;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn it_summarises_the_solve_on_ctrl_c() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&[
+            "solve",
+            "-b",
+            "helloworld",
+            "-t",
+            "0000000000000100000000000000000000000000000000000000000000000000",
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // long enough for the handler to be installed and the search to start
+    thread::sleep(Duration::from_secs(1));
+    let start_time = Instant::now();
+    let signalled = Command::new("kill")
+        .args(&["-INT", &child.id().to_string()])
+        .status()
+        .unwrap();
+    assert!(signalled.success());
+    let status = child.wait().unwrap();
+    assert!(start_time.elapsed() < Duration::from_secs(10));
+    assert_eq!(status.code(), Some(130));
+
+    let mut stdout = String::new();
+    child
+        .stdout
+        .take()
+        .unwrap()
+        .read_to_string(&mut stdout)
+        .unwrap();
+    let mut stderr = String::new();
+    child
+        .stderr
+        .take()
+        .unwrap()
+        .read_to_string(&mut stderr)
+        .unwrap();
+    assert_eq!(stdout, "No solution found: interrupted\n");
+    let summary = stderr
+        .lines()
+        .find(|line| line.starts_with("Interrupted after "))
+        .expect("Expected a summary of the solve");
+    assert!(summary.contains(" attempts, reaching nonce "));
+}
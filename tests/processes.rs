@@ -0,0 +1,50 @@
+#![cfg(unix)]
+use std::process::Command;
+
+const TARGET: &str = "00ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+
+// the nonce and hash lines of a text solve
+fn solve(extra_args: &[&str]) -> Vec<String> {
+    let output = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&["solve", "-b", "helloworld", "-t", TARGET])
+        .args(extra_args)
+        .output()
+        .unwrap();
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter(|line| line.starts_with("Solved with nonce") || line.starts_with("Hash"))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn it_finds_the_same_solution_in_processes_as_in_threads() {
+    let in_threads = solve(&["-p", "1"]);
+    assert_eq!(in_threads.len(), 2);
+    assert_eq!(solve(&["--processes", "1", "-p", "1"]), in_threads);
+}
+
+#[test]
+fn it_rejects_options_the_processes_would_ignore() {
+    for option in &[
+        ["--max-attempts", "1000"],
+        ["--deadline", "1s"],
+        ["--device", "cpu"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+            .args(&[
+                "solve",
+                "-b",
+                "helloworld",
+                "-t",
+                TARGET,
+                "--processes",
+                "2",
+            ])
+            .args(option)
+            .output()
+            .unwrap();
+        assert!(!output.status.success(), "accepted {}", option[0]);
+        assert!(output.stdout.is_empty());
+    }
+}
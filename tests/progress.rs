@@ -0,0 +1,25 @@
+use std::process::Command;
+
+// progress must stay out of stdout so pipelines can capture just the result
+#[test]
+fn it_writes_progress_to_stderr_and_the_result_to_stdout() {
+    let output = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&[
+            "solve",
+            "-b",
+            "helloworld",
+            "-t",
+            "0000000000000100000000000000000000000000000000000000000000000000",
+            "--max-attempts",
+            "200000",
+        ])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert_eq!(
+        stdout,
+        "No solution found: attempt limit of 200000 reached\n"
+    );
+    assert!(stderr.contains("Hash Rate:"));
+}
@@ -0,0 +1,26 @@
+use std::process::Command;
+
+#[test]
+fn it_solves_only_within_the_range() {
+    let output = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&[
+            "solve",
+            "-b",
+            "helloworld",
+            "-t",
+            &format!("00{}", "ff".repeat(31)),
+        ])
+        .args(&["--start", "1000", "--count", "5000"])
+        .output()
+        .unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let nonce: u64 = stdout
+        .lines()
+        .find(|line| line.starts_with("Solved with nonce: "))
+        .unwrap()
+        .trim_start_matches("Solved with nonce: ")
+        .trim_end_matches(',')
+        .parse()
+        .unwrap();
+    assert!(nonce >= 1000 && nonce < 6000);
+}
@@ -0,0 +1,61 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn it_solves_jsonl_challenges_in_order() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&["solve", "--stdin-jsonl"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let target = "0fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff";
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        writeln!(stdin, r#"{{"base":"helloworld","target":"{}"}}"#, target).unwrap();
+        writeln!(stdin, r#"{{"base":"goodbyeworld","target":"{}"}}"#, target).unwrap();
+    }
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(results.len(), 2);
+    for (i, result) in results.iter().enumerate() {
+        assert_eq!(result["line"].as_u64(), Some(i as u64 + 1));
+        assert!(result["nonce"].as_u64().is_some());
+        assert!(result["error"].is_null());
+    }
+}
+
+#[test]
+fn it_reports_a_malformed_line_and_keeps_going() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&["solve", "--stdin-jsonl"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    {
+        let stdin = child.stdin.as_mut().unwrap();
+        writeln!(stdin, "not json").unwrap();
+        writeln!(
+            stdin,
+            r#"{{"base":"helloworld","target":"ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"}}"#
+        )
+        .unwrap();
+    }
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let results: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[0]["error"].as_str().is_some());
+    assert!(results[0]["nonce"].is_null());
+    assert_eq!(results[1]["nonce"].as_u64(), Some(0));
+}
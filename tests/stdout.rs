@@ -0,0 +1,36 @@
+use std::process::Command;
+
+#[test]
+fn it_writes_only_the_target_to_stdout() {
+    let output = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&["make_target", "--duration", "10s", "--hashrate", "10"])
+        .env("RUST_LOG", "debug")
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // 10 h/s for 10s is 100 expected hashes
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "028f5c28f5c28f5c28f5c28f5c28f5c28f5c28f5c28f5c28f5c28f5c28f5c28f\n"
+    );
+}
+
+#[test]
+fn it_writes_only_the_target_bytes_with_an_estimate() {
+    let output = Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .args(&[
+            "make_target",
+            "--leading-zeros",
+            "8",
+            "--format",
+            "bytes",
+            "--estimate",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // 8 leading zero bits is 2^248
+    let mut expected = vec![0u8; 32];
+    expected[0] = 1;
+    assert_eq!(output.stdout, expected);
+}
@@ -0,0 +1,24 @@
+use std::process::Command;
+
+fn make_target(args: &[&str]) -> std::process::Output {
+    Command::new(env!("CARGO_BIN_EXE_pow_key"))
+        .arg("make_target")
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn it_rejects_more_than_one_way_of_describing_the_target() {
+    let rejected: [&[&str]; 3] = [
+        &["--difficulty", "1", "--duration", "1h", "--hashrate", "10"],
+        &["--target", &"0f".repeat(32), "--leading-zeros", "8"],
+        &["--nbits", "1d00ffff", "--difficulty", "1"],
+    ];
+    for args in rejected.iter() {
+        let output = make_target(args);
+        assert!(!output.status.success(), "accepted {:?}", args);
+        assert!(output.stdout.is_empty());
+    }
+    assert!(make_target(&["--difficulty", "1"]).status.success());
+}